@@ -1,10 +1,10 @@
 use fig::html::*;
+use fig::http::HttpError;
 use fig::select;
 use fig::socket::Socket;
 use fig::timer::Timer;
 use fig::*;
 use log::{info, trace};
-use reqwest::Client;
 use serde::{Deserialize, Serialize};
 
 // TODO remove this dependency
@@ -59,7 +59,7 @@ enum Msg {
     AddLi,
     RmLi,
     FetchSelected(String),
-    FetchedSelected(String),
+    FetchedSelected(Result<Data, HttpError>),
     Route(Route),
     SocketMessage(SocketMsg),
     ToggleTicker,
@@ -79,9 +79,20 @@ impl fig::Model for Model {
         info!("Update model with message: {:?}", msg);
         match msg {
             Msg::Select(select) => self.select = select,
-            Msg::FetchSelected(val) => return Cmd::spawn(fetch_selected(val)),
-            Msg::FetchedSelected(val) => {
-                self.server_says = Some(val);
+            Msg::FetchSelected(val) => {
+                info!("Fetch: '{}'", val);
+                let data = Data { data: val };
+                return Cmd::post_json::<Data>(
+                    "http://localhost:8000/api",
+                    &data,
+                    Msg::FetchedSelected,
+                );
+            }
+            Msg::FetchedSelected(Ok(data)) => {
+                self.server_says = Some(data.data);
+            }
+            Msg::FetchedSelected(Err(e)) => {
+                self.server_says = Some(format!("Error: {}", e));
             }
             Msg::ToggleCheck => {
                 self.check = !self.check;
@@ -133,14 +144,14 @@ impl fig::Model for Model {
             div!(
                 input!(
                     value(self.input.clone()),
-                    on_input((), |(), input| Msg::Input(input)),
+                    on_input(Msg::Input),
                     placeholder("placeholder")
                 ),
                 p!(i!("Boldly repeat: "), b!(self.input.clone()))
             ),
             p!(class!("bluesy"), "Classy!"),
             div!(button!(
-                on_click((), |()| Msg::ButtonClick),
+                on_click(|| Msg::ButtonClick),
                 format!("Clicked: {}", self.click_ct),
             ),),
             div!(
@@ -150,7 +161,7 @@ impl fig::Model for Model {
                     } else {
                         "Ticker: Off"
                     },
-                    on_click((), |()| Msg::ToggleTicker)
+                    on_click(|| Msg::ToggleTicker)
                 ),
                 if self.ticker && self.tick_on {
                     Some(span!("TICK!"))
@@ -165,21 +176,22 @@ impl fig::Model for Model {
                     } else {
                         "Websocket: Disconnect"
                     },
-                    on_click((), |()| Msg::ToggleSocket)
+                    on_click(|| Msg::ToggleSocket)
                 ),
                 span!(format!("State: {:?}", self.socket)),
             ),
             div!(
                 select!(
-                    on_input((), |(), sel| Msg::Select(sel)),
+                    on_input(Msg::Select),
                     option!(value("this"), "this"),
                     option!(value("that"), "that"),
                     option!(value("other"), "other"),
                 ),
                 button!(
-                    on_click(self.select.clone(), |select| Msg::FetchSelected(
-                        select.clone()
-                    )),
+                    on_click({
+                        let select = self.select.clone();
+                        move || Msg::FetchSelected(select.clone())
+                    }),
                     "Send request"
                 ),
                 p!("Our server says: ", {
@@ -200,8 +212,8 @@ impl fig::Model for Model {
                         p!(a!(href("/summary"), "View summary"))
                     ),
                     Route::Items => div!(
-                        button!(on_click((), |()| Msg::AddLi), "+ item"),
-                        button!(on_click((), |()| Msg::RmLi), "- item"),
+                        button!(on_click(|| Msg::AddLi), "+ item"),
+                        button!(on_click(|| Msg::RmLi), "- item"),
                         ul!((0..self.list_ct)
                             .map(|i| li!(format!("List item {}", i)))
                             .collect::<Vec<_>>()),
@@ -262,26 +274,6 @@ enum SocketMsg {
     Closed,
 }
 
-async fn fetch_selected(val: String) -> Cmd<Msg> {
-    _fetch_selected(val).await.map(Cmd::msg).unwrap_or(Cmd::none())
-}
-
-async fn _fetch_selected(val: String) -> Option<Msg> {
-    info!("Fetch: '{}'", val);
-    let body = serde_json::to_string(&Data { data: val }).unwrap();
-    let response = Client::new()
-        .post("http://localhost:8000/api")
-        .header("Content-Type", "application/json")
-        .body(body)
-        .send()
-        .await
-        .ok()?;
-
-    let text = response.text().await.ok()?;
-    let data: Data = serde_json::from_str(&text).ok()?;
-    Some(Msg::FetchedSelected(data.data))
-}
-
 #[wasm_bindgen]
 pub fn render() {
     console_log::init_with_level(log::Level::Debug).unwrap();
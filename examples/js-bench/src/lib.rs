@@ -178,7 +178,7 @@ impl fig::Model for Model {
                                     type_("button"),
                                     class!("btn", "btn-primary", "btn-block"),
                                     id("run"),
-                                    on_click((), |_| Msg::Run(1_000)),
+                                    on_click(|| Msg::Run(1_000)),
                                     "Create 1,000 rows"
                                 )
                             ),
@@ -188,7 +188,7 @@ impl fig::Model for Model {
                                     type_("button"),
                                     class!("btn", "btn-primary", "btn-block"),
                                     id("runlots"),
-                                    on_click((), |_| Msg::Run(10_000)),
+                                    on_click(|| Msg::Run(10_000)),
                                     "Create 10,000 rows"
                                 )
                             ),
@@ -198,7 +198,7 @@ impl fig::Model for Model {
                                     type_("button"),
                                     class!("btn", "btn-primary", "btn-block"),
                                     id("add"),
-                                    on_click((), |_| Msg::Add(1_000)),
+                                    on_click(|| Msg::Add(1_000)),
                                     "Append 1,000 rows"
                                 )
                             ),
@@ -208,7 +208,7 @@ impl fig::Model for Model {
                                     type_("button"),
                                     class!("btn", "btn-primary", "btn-block"),
                                     id("update"),
-                                    on_click((), |_| Msg::Update(10)),
+                                    on_click(|| Msg::Update(10)),
                                     "Update every 10th row"
                                 )
                             ),
@@ -218,7 +218,7 @@ impl fig::Model for Model {
                                     type_("button"),
                                     class!("btn", "btn-primary", "btn-block"),
                                     id("clear"),
-                                    on_click((), |_| Msg::Clear),
+                                    on_click(|| Msg::Clear),
                                     "Clear"
                                 )
                             ),
@@ -228,7 +228,7 @@ impl fig::Model for Model {
                                     type_("button"),
                                     class!("btn", "btn-primary", "btn-block"),
                                     id("swaprows"),
-                                    on_click((), |_| Msg::Swap),
+                                    on_click(|| Msg::Swap),
                                     "Swap Rows"
                                 )
                             ),
@@ -252,14 +252,14 @@ impl fig::Model for Model {
                                 td!(class!("col-md-1"), id.to_string()),
                                 td!(
                                     class!("col-md-4"),
-                                    on_click(id, |id| Msg::Select(*id)),
+                                    on_click(move || Msg::Select(id)),
                                     a!(class!("lbl"), row.label.clone()),
                                 ),
                                 td!(
                                     class!("col-md-1"),
                                     a!(
                                         class!("remove"),
-                                        on_click(id, |id| Msg::Remove(*id)),
+                                        on_click(move || Msg::Remove(id)),
                                         span!(class!("glyphicon", "glyphicon-remove", "remove"))
                                     )
                                 ),
@@ -276,5 +276,5 @@ impl fig::Model for Model {
 
 #[wasm_bindgen]
 pub fn render() {
-    fig::run(Model::new(), "app").expect("Failed to start app")
+    fig::run(Model::new(), "app").expect("Failed to start app");
 }
@@ -25,7 +25,7 @@ impl fig::Model for Model {
         div![
             style!("font-family" => "Arial", "text-align" => "center"),
             h1!("Hello from fig!"),
-            button!("Click me!", on_click((), |()| Msg::ButtonClicked)),
+            button!("Click me!", on_click(|| Msg::ButtonClicked)),
             p!(format!("Click count: {}", self.click_count))
         ]
     }
@@ -33,5 +33,5 @@ impl fig::Model for Model {
 
 #[wasm_bindgen]
 pub fn render() {
-    fig::run(Model { click_count: 0 }, "app").expect("Failed to start app")
+    fig::run(Model { click_count: 0 }, "app").expect("Failed to start app");
 }
@@ -1,3 +1,4 @@
+use fig::event::on_check;
 use fig::html::*;
 use fig::*;
 use wasm_bindgen::prelude::*;
@@ -27,7 +28,7 @@ fn test() -> Model {
     }
 }
 
-#[derive(Debug, Clone, Hash)]
+#[derive(Debug, Clone)]
 struct Todo {
     text: String,
     completed: bool,
@@ -44,7 +45,7 @@ impl Todo {
     }
 }
 
-#[derive(Debug, Clone, Hash)]
+#[derive(Debug, Clone)]
 pub enum Route {
     All,
     Active,
@@ -68,48 +69,21 @@ impl Todo {
                 input!(
                     class!("toggle"),
                     type_("checkbox"),
-                    on_click((ix, self.clone()), |(ix, t)| {
-                        let mut t = t.clone();
-                        t.completed = !t.completed;
-                        Msg::TodoUpdate {
-                            index: *ix,
-                            todo: t,
-                        }
-                    })
-                ),
-                label!(
-                    self.text.clone(),
-                    on_dbl_click((ix, self.clone()), |(ix, t)| {
-                        let mut t = t.clone();
-                        t.editing = true;
-                        Msg::TodoUpdate {
-                            index: *ix,
-                            todo: t,
-                        }
-                    })
+                    if self.completed {
+                        Some(checked())
+                    } else {
+                        None
+                    },
+                    on_check(move |done| Msg::SetCompleted(ix, done))
                 ),
-                button!(class!("destroy"), on_click(ix, |ix| Msg::RemoveTodo(*ix)))
+                label!(self.text.clone(), on_dbl_click(move || Msg::EditTodo(ix))),
+                button!(class!("destroy"), on_click(move || Msg::RemoveTodo(ix)))
             ),
             input!(
                 class!("edit"),
                 value(self.text.clone()),
-                // TODO I don't like this. It was confusing. Opaque error message
-                on_input((ix, self.clone()), |(ix, t), s| {
-                    let mut t = t.clone();
-                    t.text = s;
-                    Msg::TodoUpdate {
-                        index: *ix,
-                        todo: t,
-                    }
-                }),
-                on_keydown("Enter", (ix, self.clone()), |(ix, t)| {
-                    let mut t = t.clone();
-                    t.editing = false;
-                    Msg::TodoUpdate {
-                        index: *ix,
-                        todo: t,
-                    }
-                }),
+                on_input(move |text| Msg::TodoText(ix, text)),
+                on_keydown("Enter", move || Msg::FinishEdit(ix)),
             )
         )
     }
@@ -118,7 +92,10 @@ impl Todo {
 enum Msg {
     NewTodoUpdate(String),
     NewTodo(String),
-    TodoUpdate { index: usize, todo: Todo },
+    SetCompleted(usize, bool),
+    EditTodo(usize),
+    TodoText(usize, String),
+    FinishEdit(usize),
     RemoveTodo(usize),
     Route(Route),
 }
@@ -135,9 +112,10 @@ impl fig::Model for Model {
                 self.todos.push(Todo::new(text));
                 self.wip = String::new();
             }
-            Msg::TodoUpdate { index, todo } => {
-                self.todos[index] = todo;
-            }
+            Msg::SetCompleted(ix, completed) => self.todos[ix].completed = completed,
+            Msg::EditTodo(ix) => self.todos[ix].editing = true,
+            Msg::TodoText(ix, text) => self.todos[ix].text = text,
+            Msg::FinishEdit(ix) => self.todos[ix].editing = false,
             Msg::RemoveTodo(ix) => {
                 self.todos.remove(ix);
             }
@@ -176,8 +154,11 @@ impl fig::Model for Model {
             input![
                 class!("new-todo"),
                 placeholder("What needs to be done?"),
-                on_input((), |(), s| Msg::NewTodoUpdate(s.clone())),
-                on_keydown("Enter", self.wip.clone(), |wip| Msg::NewTodo(wip.clone())),
+                on_input(Msg::NewTodoUpdate),
+                on_keydown("Enter", {
+                    let wip = self.wip.clone();
+                    move || Msg::NewTodo(wip.clone())
+                }),
                 value(self.wip.clone()),
                 self.wip.clone()
             ]
@@ -207,7 +188,7 @@ impl fig::Model for Model {
                             href("/"),
                             "All",
                         ),
-                        on_click((), |()| Msg::Route(Route::All))
+                        on_click(|| Msg::Route(Route::All))
                     ),
                     span!(" "),
                     li!(
@@ -220,7 +201,7 @@ impl fig::Model for Model {
                             href("/active"),
                             "Active"
                         ),
-                        on_click((), |()| Msg::Route(Route::Active))
+                        on_click(|| Msg::Route(Route::Active))
                     ),
                     span!(" "),
                     li!(
@@ -233,7 +214,7 @@ impl fig::Model for Model {
                             href("/completed"),
                             "Completed"
                         ),
-                        on_click((), |()| Msg::Route(Route::Completed))
+                        on_click(|| Msg::Route(Route::Completed))
                     )
                 ]
             )
@@ -272,5 +253,5 @@ impl fig::Model for Model {
 
 #[wasm_bindgen]
 pub fn run() {
-    fig::run(test(), "app").expect("Failed to start app")
+    fig::run(test(), "app").expect("Failed to start app");
 }
@@ -0,0 +1,269 @@
+//! Subscriptions to global browser events.
+//!
+//! These attach a listener to the `window` or `document` for as long as they are
+//! returned from `Model::subscribe`, and translate each event into a message.
+//!
+//! ```ignore
+//! fn subscribe(&self) -> Sub<Self> {
+//!     let mut subs: Vec<Box<dyn Subscription<_>>> = vec![
+//!         Box::new(browser::on_resize(Msg::Resized)),
+//!         Box::new(browser::on_key_down(Msg::KeyDown)),
+//!     ];
+//!     if self.unsaved_changes {
+//!         subs.push(Box::new(browser::warn_before_unload()));
+//!     }
+//!     Sub::new(subs)
+//! }
+//! ```
+
+use std::fmt::{self, Debug};
+use std::ptr;
+
+use wasm_bindgen::JsCast;
+use web_sys::{BeforeUnloadEvent, Event as DomEvent, EventTarget};
+
+use crate::event::{self, FromEvent, KeyInfo, Listener};
+use crate::{current_url, Cmd, CmdInner, Key, Model, Subscription, Url};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Target {
+    Window,
+    Document,
+}
+
+/// The function producing a message from an event
+enum Handler<Msg, T> {
+    Payload(fn(T) -> Msg),
+    Plain(fn() -> Msg),
+    /// The event is only listened to for its side effects
+    None,
+}
+
+// Derives would needlessly require Msg and T to implement the traits
+impl<Msg, T> Clone for Handler<Msg, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<Msg, T> Copy for Handler<Msg, T> {}
+
+impl<Msg, T> PartialEq for Handler<Msg, T> {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Handler::Payload(f1), Handler::Payload(f2)) => ptr::fn_addr_eq(*f1, *f2),
+            (Handler::Plain(f1), Handler::Plain(f2)) => ptr::fn_addr_eq(*f1, *f2),
+            (Handler::None, Handler::None) => true,
+            _ => false,
+        }
+    }
+}
+
+/// A subscription to an event on the window or document.
+/// Constructed with the functions in this module
+pub struct BrowserEvent<M: Model, T> {
+    target: Target,
+    events: &'static [&'static str],
+    decode: fn(&DomEvent) -> T,
+    handler: Handler<M::Msg, T>,
+    listeners: Vec<Listener<M>>,
+}
+
+impl<M: Model, T> BrowserEvent<M, T> {
+    fn new(
+        target: Target,
+        events: &'static [&'static str],
+        decode: fn(&DomEvent) -> T,
+        handler: Handler<M::Msg, T>,
+    ) -> BrowserEvent<M, T> {
+        BrowserEvent {
+            target,
+            events,
+            decode,
+            handler,
+            listeners: Vec::new(),
+        }
+    }
+}
+
+impl<M: Model, T> Debug for BrowserEvent<M, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "BrowserEvent({:?} {:?})", self.target, self.events)
+    }
+}
+
+impl<M: Model, T> PartialEq for BrowserEvent<M, T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.target == other.target
+            && self.events == other.events
+            && ptr::fn_addr_eq(self.decode, other.decode)
+            && self.handler == other.handler
+    }
+}
+
+impl<M: Model, T: 'static> Subscription<M> for BrowserEvent<M, T> {
+    fn subscribe(&mut self, key: Key<M>) {
+        let window = web_sys::window().expect("No global `window` exists");
+        let target: EventTarget = match self.target {
+            Target::Window => window.into(),
+            Target::Document => window.document().expect("No document").into(),
+        };
+        for &name in self.events {
+            let decode = self.decode;
+            let handler = self.handler;
+            let listener = event::attach_event_handler(key, target.clone(), name, move |ev| {
+                let payload = decode(&ev);
+                match handler {
+                    Handler::Payload(handler) => Cmd::msg(handler(payload)),
+                    Handler::Plain(handler) => Cmd::msg(handler()),
+                    Handler::None => Cmd(CmdInner::NoOp),
+                }
+            });
+            match listener {
+                Ok(listener) => self.listeners.push(listener),
+                Err(e) => error!("Failed to listen for '{}': {:?}", name, e),
+            }
+        }
+    }
+
+    fn sub_eq(&self, other: &dyn Subscription<M>) -> bool {
+        if let Some(o) = other.downcast_ref::<Self>() {
+            self == o
+        } else {
+            false
+        }
+    }
+}
+
+/// The size of the browser viewport, in pixels
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WindowSize {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl WindowSize {
+    /// The current size of the viewport
+    pub fn current() -> WindowSize {
+        let window = web_sys::window().expect("No global `window` exists");
+        let dimension = |val: Result<wasm_bindgen::JsValue, _>| {
+            val.ok().and_then(|v| v.as_f64()).unwrap_or(0.) as u32
+        };
+        WindowSize {
+            width: dimension(window.inner_width()),
+            height: dimension(window.inner_height()),
+        }
+    }
+}
+
+/// Whether the page is visible, e.g. hidden if the user switches tabs
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Visibility {
+    Visible,
+    Hidden,
+}
+
+/// Fires when the browser window is resized
+pub fn on_resize<M: Model>(handler: fn(WindowSize) -> M::Msg) -> BrowserEvent<M, WindowSize> {
+    BrowserEvent::new(
+        Target::Window,
+        &["resize"],
+        |_| WindowSize::current(),
+        Handler::Payload(handler),
+    )
+}
+
+/// Fires when any key is pressed, wherever the focus is
+pub fn on_key_down<M: Model>(handler: fn(KeyInfo) -> M::Msg) -> BrowserEvent<M, KeyInfo> {
+    BrowserEvent::new(
+        Target::Document,
+        &["keydown"],
+        KeyInfo::from_event,
+        Handler::Payload(handler),
+    )
+}
+
+/// Fires when any key is released, wherever the focus is
+pub fn on_key_up<M: Model>(handler: fn(KeyInfo) -> M::Msg) -> BrowserEvent<M, KeyInfo> {
+    BrowserEvent::new(
+        Target::Document,
+        &["keyup"],
+        KeyInfo::from_event,
+        Handler::Payload(handler),
+    )
+}
+
+/// Fires when the page is hidden or shown, e.g. by switching tabs or minimizing the window
+pub fn on_visibility_change<M: Model>(
+    handler: fn(Visibility) -> M::Msg,
+) -> BrowserEvent<M, Visibility> {
+    BrowserEvent::new(
+        Target::Document,
+        &["visibilitychange"],
+        |_| {
+            let document = web_sys::window()
+                .and_then(|w| w.document())
+                .expect("No document");
+            if document.hidden() {
+                Visibility::Hidden
+            } else {
+                Visibility::Visible
+            }
+        },
+        Handler::Payload(handler),
+    )
+}
+
+/// Fires when the browser goes online or offline, passing `true` if now online
+pub fn on_online_change<M: Model>(handler: fn(bool) -> M::Msg) -> BrowserEvent<M, bool> {
+    BrowserEvent::new(
+        Target::Window,
+        &["online", "offline"],
+        |ev| ev.type_() == "online",
+        Handler::Payload(handler),
+    )
+}
+
+/// Fires when the fragment of the url (the part after '#') changes, passing the new url
+pub fn on_hash_change<M: Model>(handler: fn(Url) -> M::Msg) -> BrowserEvent<M, Url> {
+    BrowserEvent::new(
+        Target::Window,
+        &["hashchange"],
+        |_| {
+            let window = web_sys::window().expect("No global `window` exists");
+            current_url(&window).expect("No location")
+        },
+        Handler::Payload(handler),
+    )
+}
+
+/// Fires when the user is about to leave the page, e.g. to save state.
+///
+/// Note that `update` is run, but commands which do not complete immediately
+/// (such as spawned futures) may not get a chance to run.
+pub fn on_before_unload<M: Model>(handler: fn() -> M::Msg) -> BrowserEvent<M, ()> {
+    BrowserEvent::new(
+        Target::Window,
+        &["beforeunload"],
+        |_| (),
+        Handler::Plain(handler),
+    )
+}
+
+/// Ask the user to confirm before leaving the page, e.g. while there are unsaved changes.
+///
+/// Subscribe to this only while the confirmation is needed.
+pub fn warn_before_unload<M: Model>() -> BrowserEvent<M, ()> {
+    BrowserEvent::new(
+        Target::Window,
+        &["beforeunload"],
+        |ev| {
+            ev.prevent_default();
+            // Required by some browsers. The text itself is never shown
+            if let Some(ev) = ev.dyn_ref::<BeforeUnloadEvent>() {
+                ev.set_return_value("");
+            }
+        },
+        Handler::None,
+    )
+}
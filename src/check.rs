@@ -0,0 +1,667 @@
+//! Debug-mode consistency checking of the rendered DOM.
+//!
+//! `fig` assumes that it is the only thing modifying the DOM beneath the app target.
+//! If some other script moves, removes or edits our nodes, the index bookkeeping
+//! in the diff renderer will silently go wrong and the page becomes corrupted.
+//!
+//! When enabled with `set_mode`, the live DOM is walked after every render and
+//! compared against the virtual DOM. The first divergence found is logged along
+//! with the path of child indices leading to it, and the app can optionally be
+//! re-rendered from scratch.
+//!
+//! Checking is expensive - it walks the whole tree - so it is off by default,
+//! and the module is only compiled into debug builds. Calls to `set_mode` should
+//! likewise be wrapped in `#[cfg(debug_assertions)]`.
+//!
+//! As well as attributes, the live `value`, `checked` and `selected` properties of
+//! form controls are compared, as these change when the user interacts with the page.
+
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::fmt;
+
+use wasm_bindgen::JsCast;
+use web_sys::{Element as DomElement, Node};
+
+use crate::{Html, Model};
+
+/// Whether (and how) to check the DOM after each render
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CheckMode {
+    /// Do not check the DOM (the default)
+    Off,
+    /// Log any divergence between the DOM and the virtual DOM
+    Report,
+    /// Log any divergence, then re-render the app from scratch
+    Repair,
+}
+
+thread_local! {
+    static MODE: Cell<CheckMode> = const { Cell::new(CheckMode::Off) };
+}
+
+/// Set the DOM checking mode for all apps on the page
+pub fn set_mode(mode: CheckMode) {
+    MODE.with(|m| m.set(mode))
+}
+
+/// Get the current DOM checking mode
+pub fn mode() -> CheckMode {
+    MODE.with(|m| m.get())
+}
+
+/// The first point at which the DOM differs from the virtual DOM
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Divergence {
+    /// Child indices leading from the app root to the divergent node
+    pub path: Vec<usize>,
+    pub mismatch: Mismatch,
+}
+
+/// The way in which a DOM node differs from its virtual counterpart
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Mismatch {
+    /// Expected an element but found a text node, or vice versa
+    NodeType {
+        expected: &'static str,
+        found: String,
+    },
+    Tag {
+        expected: String,
+        found: String,
+    },
+    Attribute {
+        name: String,
+        expected: Option<String>,
+        found: Option<String>,
+    },
+    /// The live `value`, `checked` or `selected` property of a form control
+    Property {
+        name: &'static str,
+        expected: String,
+        found: String,
+    },
+    Text {
+        expected: String,
+        found: String,
+    },
+    ChildCount {
+        expected: usize,
+        found: usize,
+    },
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "root")?;
+        for ix in &self.path {
+            write!(f, "/{}", ix)?;
+        }
+        write!(f, ": {}", self.mismatch)
+    }
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use Mismatch::*;
+        match self {
+            NodeType { expected, found } => {
+                write!(f, "expected {} node, found {}", expected, found)
+            }
+            Tag { expected, found } => write!(f, "expected <{}>, found <{}>", expected, found),
+            Attribute {
+                name,
+                expected,
+                found,
+            } => write!(
+                f,
+                "attribute '{}': expected {:?}, found {:?}",
+                name, expected, found
+            ),
+            Property {
+                name,
+                expected,
+                found,
+            } => write!(
+                f,
+                "property '{}': expected {:?}, found {:?}",
+                name, expected, found
+            ),
+            Text { expected, found } => {
+                write!(f, "expected text {:?}, found {:?}", expected, found)
+            }
+            ChildCount { expected, found } => {
+                write!(f, "expected {} children, found {}", expected, found)
+            }
+        }
+    }
+}
+
+/// Check the DOM beneath `target` according to `mode`, logging any divergence.
+/// Returns whether the app should be re-rendered from scratch.
+pub(crate) fn run<N: DomNode, M: Model>(mode: CheckMode, target: &N, vdom: &Html<M>) -> bool {
+    if mode == CheckMode::Off {
+        return false;
+    }
+    match check_dom(target, vdom) {
+        Ok(()) => false,
+        Err(divergence) => {
+            error!("DOM has diverged from vdom at {}", divergence);
+            mode == CheckMode::Repair
+        }
+    }
+}
+
+/// The parts of a DOM node which are compared against the virtual DOM.
+/// Implemented for `web_sys::Node`, and for a plain tree in the tests.
+pub(crate) trait DomNode: Sized {
+    /// The lowercased node name, e.g. `#text` or `div`
+    fn name(&self) -> String;
+    /// The content of a text node, or `None` for any other kind of node
+    fn text(&self) -> Option<String>;
+    fn is_element(&self) -> bool;
+    fn attribute(&self, name: &str) -> Option<String>;
+    fn attribute_names(&self) -> Vec<String>;
+    /// The live value of a form control property, or `None` if the node has no such property
+    fn property(&self, name: &str) -> Option<String>;
+    fn children(&self) -> Vec<Self>;
+}
+
+impl DomNode for Node {
+    fn name(&self) -> String {
+        self.node_name().to_lowercase()
+    }
+
+    fn text(&self) -> Option<String> {
+        if self.node_type() == Node::TEXT_NODE {
+            Some(self.text_content().unwrap_or_default())
+        } else {
+            None
+        }
+    }
+
+    fn is_element(&self) -> bool {
+        self.dyn_ref::<DomElement>().is_some()
+    }
+
+    fn attribute(&self, name: &str) -> Option<String> {
+        self.dyn_ref::<DomElement>()?.get_attribute(name)
+    }
+
+    fn attribute_names(&self) -> Vec<String> {
+        match self.dyn_ref::<DomElement>() {
+            Some(el) => el
+                .get_attribute_names()
+                .iter()
+                .map(|name| name.as_string().unwrap_or_default())
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    fn property(&self, name: &str) -> Option<String> {
+        use web_sys::{HtmlInputElement, HtmlOptionElement, HtmlTextAreaElement};
+        match name {
+            "value" => {
+                if let Some(input) = self.dyn_ref::<HtmlInputElement>() {
+                    Some(input.value())
+                } else {
+                    self.dyn_ref::<HtmlTextAreaElement>().map(|t| t.value())
+                }
+            }
+            "checked" => self
+                .dyn_ref::<HtmlInputElement>()
+                .map(|input| input.checked().to_string()),
+            "selected" => self
+                .dyn_ref::<HtmlOptionElement>()
+                .map(|option| option.selected().to_string()),
+            _ => None,
+        }
+    }
+
+    fn children(&self) -> Vec<Node> {
+        let children = self.child_nodes();
+        (0..children.length())
+            .map(|ix| children.get(ix).expect("index in range"))
+            .collect()
+    }
+}
+
+/// Compare the DOM beneath `target` against the virtual DOM.
+///
+/// `target` is the element the app is mounted on, so it should have
+/// exactly one child, corresponding to the root of `vdom`.
+pub(crate) fn check_dom<N: DomNode, M: Model>(
+    target: &N,
+    vdom: &Html<M>,
+) -> Result<(), Divergence> {
+    let mut path = Vec::new();
+    let children = target.children();
+    if children.len() != 1 {
+        return Err(Divergence {
+            path,
+            mismatch: Mismatch::ChildCount {
+                expected: 1,
+                found: children.len(),
+            },
+        });
+    }
+    check_node(&children[0], vdom, &mut path)
+}
+
+fn check_node<N: DomNode, M: Model>(
+    node: &N,
+    vdom: &Html<M>,
+    path: &mut Vec<usize>,
+) -> Result<(), Divergence> {
+    let fail = |path: &Vec<usize>, mismatch| {
+        Err(Divergence {
+            path: path.clone(),
+            mismatch,
+        })
+    };
+    match vdom {
+        Html::Text(text) => {
+            let found = match node.text() {
+                Some(found) => found,
+                None => {
+                    return fail(
+                        path,
+                        Mismatch::NodeType {
+                            expected: "text",
+                            found: node.name(),
+                        },
+                    )
+                }
+            };
+            if found != *text {
+                return fail(
+                    path,
+                    Mismatch::Text {
+                        expected: text.to_string(),
+                        found,
+                    },
+                );
+            }
+            Ok(())
+        }
+        Html::Element(elem) => {
+            if !node.is_element() {
+                return fail(
+                    path,
+                    Mismatch::NodeType {
+                        expected: "element",
+                        found: node.name(),
+                    },
+                );
+            }
+            let expected_tag = elem.tag.to_string();
+            let found_tag = node.name();
+            if expected_tag != found_tag {
+                return fail(
+                    path,
+                    Mismatch::Tag {
+                        expected: expected_tag,
+                        found: found_tag,
+                    },
+                );
+            }
+
+            // Later attributes overwrite earlier ones, just like `set_attribute`
+            let expected_attrs: BTreeMap<String, String> = elem
+                .attrs
+                .iter()
+                .map(|attr| (attr.key().to_string(), attr.value().to_string()))
+                .collect();
+            for (name, expected) in &expected_attrs {
+                let found = node.attribute(name);
+                if found.as_ref() != Some(expected) {
+                    return fail(
+                        path,
+                        Mismatch::Attribute {
+                            name: name.clone(),
+                            expected: Some(expected.clone()),
+                            found,
+                        },
+                    );
+                }
+            }
+            for name in node.attribute_names() {
+                if !expected_attrs.contains_key(&name) {
+                    let found = node.attribute(&name);
+                    return fail(
+                        path,
+                        Mismatch::Attribute {
+                            name,
+                            expected: None,
+                            found,
+                        },
+                    );
+                }
+            }
+
+            // The attributes only set the initial state of form controls, so compare the
+            // live properties too. A missing `value` attribute is left to the browser
+            let expected_props = [
+                ("value", expected_attrs.get("value").cloned()),
+                (
+                    "checked",
+                    Some(expected_attrs.contains_key("checked").to_string()),
+                ),
+                (
+                    "selected",
+                    Some(expected_attrs.contains_key("selected").to_string()),
+                ),
+            ];
+            for (name, expected) in expected_props.iter() {
+                if let (Some(expected), Some(found)) = (expected, node.property(name)) {
+                    if *expected != found {
+                        return fail(
+                            path,
+                            Mismatch::Property {
+                                name,
+                                expected: expected.clone(),
+                                found,
+                            },
+                        );
+                    }
+                }
+            }
+
+            let children = node.children();
+            if children.len() != elem.children.len() {
+                return fail(
+                    path,
+                    Mismatch::ChildCount {
+                        expected: elem.children.len(),
+                        found: children.len(),
+                    },
+                );
+            }
+            for (ix, (child_node, child)) in children.iter().zip(&elem.children).enumerate() {
+                path.push(ix);
+                check_node(child_node, child, path)?;
+                path.pop();
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::html::{id, value, Attribute, Tag};
+    use crate::Element;
+
+    /// A plain stand-in for the live DOM
+    #[derive(Clone)]
+    enum TestNode {
+        Text(&'static str),
+        Element(
+            &'static str,
+            Vec<(&'static str, &'static str)>,
+            Vec<TestNode>,
+        ),
+        /// An `<input>` with its attributes and live properties
+        Input(
+            Vec<(&'static str, &'static str)>,
+            Vec<(&'static str, &'static str)>,
+        ),
+    }
+
+    impl DomNode for TestNode {
+        fn name(&self) -> String {
+            match self {
+                TestNode::Text(_) => "#text".into(),
+                TestNode::Element(tag, ..) => tag.to_string(),
+                TestNode::Input(..) => "input".into(),
+            }
+        }
+
+        fn text(&self) -> Option<String> {
+            match self {
+                TestNode::Text(text) => Some(text.to_string()),
+                TestNode::Element(..) | TestNode::Input(..) => None,
+            }
+        }
+
+        fn is_element(&self) -> bool {
+            !matches!(self, TestNode::Text(_))
+        }
+
+        fn attribute(&self, name: &str) -> Option<String> {
+            match self {
+                TestNode::Text(_) => None,
+                TestNode::Element(_, attrs, _) | TestNode::Input(attrs, _) => attrs
+                    .iter()
+                    .find(|(k, _)| *k == name)
+                    .map(|(_, v)| v.to_string()),
+            }
+        }
+
+        fn attribute_names(&self) -> Vec<String> {
+            match self {
+                TestNode::Text(_) => Vec::new(),
+                TestNode::Element(_, attrs, _) | TestNode::Input(attrs, _) => {
+                    attrs.iter().map(|(k, _)| k.to_string()).collect()
+                }
+            }
+        }
+
+        fn property(&self, name: &str) -> Option<String> {
+            match self {
+                TestNode::Input(_, props) => props
+                    .iter()
+                    .find(|(k, _)| *k == name)
+                    .map(|(_, v)| v.to_string()),
+                _ => None,
+            }
+        }
+
+        fn children(&self) -> Vec<TestNode> {
+            match self {
+                TestNode::Text(_) => Vec::new(),
+                TestNode::Element(_, _, children) => children.clone(),
+                TestNode::Input(..) => Vec::new(),
+            }
+        }
+    }
+
+    fn mount(root: TestNode) -> TestNode {
+        TestNode::Element("div", Vec::new(), vec![root])
+    }
+
+    fn el(tag: Tag, attrs: Vec<Attribute>, children: Vec<Html<()>>) -> Html<()> {
+        let mut elem = Element::tag(tag);
+        elem.attrs = attrs;
+        elem.children = children;
+        Html::Element(elem)
+    }
+
+    fn vdom() -> Html<()> {
+        el(
+            Tag::Div,
+            vec![id("list")],
+            vec![
+                el(Tag::P, Vec::new(), vec![Html::Text("hello".into())]),
+                el(Tag::Input, vec![value("x")], Vec::new()),
+            ],
+        )
+    }
+
+    fn dom() -> TestNode {
+        use TestNode::*;
+        Element(
+            "div",
+            vec![("id", "list")],
+            vec![
+                Element("p", Vec::new(), vec![Text("hello")]),
+                Element("input", vec![("value", "x")], Vec::new()),
+            ],
+        )
+    }
+
+    #[test]
+    fn test_check_dom_matches() {
+        assert_eq!(check_dom(&mount(dom()), &vdom()), Ok(()));
+    }
+
+    #[test]
+    fn test_check_dom_mismatches() {
+        use TestNode::*;
+        let check = |root| check_dom(&mount(root), &vdom()).unwrap_err();
+
+        let found = check(Element("span", vec![("id", "list")], Vec::new()));
+        assert_eq!(found.path, Vec::<usize>::new());
+        assert_eq!(
+            found.mismatch,
+            Mismatch::Tag {
+                expected: "div".into(),
+                found: "span".into()
+            }
+        );
+
+        let found = check(Text("hello"));
+        assert_eq!(
+            found.mismatch,
+            Mismatch::NodeType {
+                expected: "element",
+                found: "#text".into()
+            }
+        );
+
+        let mut root = dom();
+        if let Element(_, attrs, _) = &mut root {
+            attrs.push(("class", "extra"));
+        }
+        assert_eq!(
+            check(root).mismatch,
+            Mismatch::Attribute {
+                name: "class".into(),
+                expected: None,
+                found: Some("extra".into())
+            }
+        );
+
+        let mut root = dom();
+        if let Element(_, _, children) = &mut root {
+            children[1] = Element("input", vec![("value", "y")], Vec::new());
+        }
+        let found = check(root);
+        assert_eq!(found.path, vec![1]);
+        assert_eq!(
+            found.mismatch,
+            Mismatch::Attribute {
+                name: "value".into(),
+                expected: Some("x".into()),
+                found: Some("y".into())
+            }
+        );
+
+        let mut root = dom();
+        if let Element(_, _, children) = &mut root {
+            children[0] = Element("p", Vec::new(), vec![Text("goodbye")]);
+        }
+        let found = check(root);
+        assert_eq!(found.path, vec![0, 0]);
+        assert_eq!(
+            found.mismatch,
+            Mismatch::Text {
+                expected: "hello".into(),
+                found: "goodbye".into()
+            }
+        );
+
+        let mut root = dom();
+        if let Element(_, _, children) = &mut root {
+            children.pop();
+        }
+        assert_eq!(
+            check(root).mismatch,
+            Mismatch::ChildCount {
+                expected: 2,
+                found: 1
+            }
+        );
+
+        let empty = Element("div", Vec::new(), Vec::new());
+        assert_eq!(
+            check_dom(&empty, &vdom()).unwrap_err().mismatch,
+            Mismatch::ChildCount {
+                expected: 1,
+                found: 0
+            }
+        );
+    }
+
+    #[test]
+    fn test_check_dom_properties() {
+        use TestNode::*;
+        let with_input = |input| {
+            mount(Element(
+                "div",
+                vec![("id", "list")],
+                vec![Element("p", Vec::new(), vec![Text("hello")]), input],
+            ))
+        };
+
+        let untouched = Input(
+            vec![("value", "x")],
+            vec![("value", "x"), ("checked", "false")],
+        );
+        assert_eq!(check_dom(&with_input(untouched), &vdom()), Ok(()));
+
+        // The attribute still matches, but the user has typed since
+        let typed = Input(
+            vec![("value", "x")],
+            vec![("value", "xy"), ("checked", "false")],
+        );
+        let found = check_dom(&with_input(typed), &vdom()).unwrap_err();
+        assert_eq!(found.path, vec![1]);
+        assert_eq!(
+            found.mismatch,
+            Mismatch::Property {
+                name: "value",
+                expected: "x".into(),
+                found: "xy".into()
+            }
+        );
+
+        let clicked = Input(
+            vec![("value", "x")],
+            vec![("value", "x"), ("checked", "true")],
+        );
+        assert_eq!(
+            check_dom(&with_input(clicked), &vdom())
+                .unwrap_err()
+                .mismatch,
+            Mismatch::Property {
+                name: "checked",
+                expected: "false".into(),
+                found: "true".into()
+            }
+        );
+    }
+
+    #[test]
+    fn test_run_repairs_only_in_repair_mode() {
+        let broken = mount(TestNode::Text("oops"));
+        assert!(!run(CheckMode::Off, &broken, &vdom()));
+        assert!(!run(CheckMode::Report, &broken, &vdom()));
+        assert!(run(CheckMode::Repair, &broken, &vdom()));
+        assert!(!run(CheckMode::Repair, &mount(dom()), &vdom()));
+    }
+
+    #[test]
+    fn test_divergence_display() {
+        let div = Divergence {
+            path: vec![0, 3, 1],
+            mismatch: Mismatch::Tag {
+                expected: "li".into(),
+                found: "span".into(),
+            },
+        };
+        assert_eq!(div.to_string(), "root/0/3/1: expected <li>, found <span>");
+    }
+}
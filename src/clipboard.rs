@@ -0,0 +1,145 @@
+//! Reading and writing the system clipboard.
+//!
+//! `Cmd::copy_to_clipboard` and `Cmd::read_clipboard` use the asynchronous Clipboard
+//! API, passing the outcome to `update`:
+//!
+//! ```ignore
+//! fn update(&mut self, msg: Msg) -> Cmd<Msg> {
+//!     match msg {
+//!         Msg::CopyLink => return Cmd::copy_to_clipboard(self.link.clone(), Msg::Copied),
+//!         Msg::Copied(Ok(())) => self.toast = Some("Copied!"),
+//!         Msg::Copied(Err(e)) => error!("{}", e),
+//!         ...
+//!     }
+//! }
+//! ```
+//!
+//! Browsers only allow clipboard access in response to a user action such as a
+//! click, and may ask the user for permission before the clipboard can be read.
+//! To receive text pasted by the user, see `event::on_paste`.
+
+use std::fmt;
+
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{HtmlDocument, HtmlTextAreaElement};
+
+use crate::Cmd;
+
+/// An error accessing the clipboard, e.g. because the user denied permission
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ClipboardError {
+    pub message: String,
+}
+
+impl ClipboardError {
+    fn new(message: impl Into<String>) -> ClipboardError {
+        ClipboardError {
+            message: message.into(),
+        }
+    }
+}
+
+impl From<JsValue> for ClipboardError {
+    fn from(e: JsValue) -> ClipboardError {
+        match e.dyn_ref::<js_sys::Error>() {
+            Some(e) => ClipboardError::new(String::from(e.message())),
+            None => ClipboardError::new(format!("{:?}", e)),
+        }
+    }
+}
+
+impl fmt::Display for ClipboardError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "clipboard error: {}", self.message)
+    }
+}
+
+impl std::error::Error for ClipboardError {}
+
+impl<Msg: 'static> Cmd<Msg> {
+    /// Copy text to the clipboard, passing the result to `update`.
+    ///
+    /// In browsers without the Clipboard API this falls back to `document.execCommand("copy")`.
+    /// The fallback copies immediately, as browsers only allow it while handling the user
+    /// action, so the command should be returned from `update` straight away.
+    pub fn copy_to_clipboard(
+        text: impl Into<String>,
+        to_msg: impl FnOnce(Result<(), ClipboardError>) -> Msg + 'static,
+    ) -> Cmd<Msg> {
+        let text = text.into();
+        match clipboard() {
+            Some(clipboard) => {
+                Cmd::spawn(async move { Cmd::msg(to_msg(write_text(&clipboard, &text).await)) })
+            }
+            None => Cmd::msg(to_msg(exec_copy(&text))),
+        }
+    }
+
+    /// Read the text on the clipboard, passing the result to `update`
+    pub fn read_clipboard(
+        to_msg: impl FnOnce(Result<String, ClipboardError>) -> Msg + 'static,
+    ) -> Cmd<Msg> {
+        Cmd::spawn(async move { Cmd::msg(to_msg(read_text().await)) })
+    }
+}
+
+/// The `navigator.clipboard` object, if supported.
+/// Accessed dynamically as the web-sys bindings are unstable
+fn clipboard() -> Option<JsValue> {
+    let navigator = web_sys::window()?.navigator();
+    js_sys::Reflect::get(&navigator, &JsValue::from_str("clipboard"))
+        .ok()
+        .filter(|clipboard| !clipboard.is_undefined())
+}
+
+fn method(clipboard: &JsValue, name: &str) -> Result<js_sys::Function, ClipboardError> {
+    let method = js_sys::Reflect::get(clipboard, &JsValue::from_str(name))?;
+    method
+        .dyn_into()
+        .map_err(|_| ClipboardError::new(format!("clipboard.{} is not supported", name)))
+}
+
+async fn resolve(promise: JsValue) -> Result<JsValue, ClipboardError> {
+    let promise: js_sys::Promise = promise.dyn_into()?;
+    Ok(JsFuture::from(promise).await?)
+}
+
+async fn write_text(clipboard: &JsValue, text: &str) -> Result<(), ClipboardError> {
+    let write = method(clipboard, "writeText")?;
+    resolve(write.call1(clipboard, &JsValue::from_str(text))?).await?;
+    Ok(())
+}
+
+async fn read_text() -> Result<String, ClipboardError> {
+    let clipboard =
+        clipboard().ok_or_else(|| ClipboardError::new("the Clipboard API is not supported"))?;
+    let read = method(&clipboard, "readText")?;
+    let text = resolve(read.call0(&clipboard)?).await?;
+    text.as_string()
+        .ok_or_else(|| ClipboardError::new("clipboard did not contain text"))
+}
+
+/// Copy by selecting the text in a temporary textarea, for older browsers
+fn exec_copy(text: &str) -> Result<(), ClipboardError> {
+    let document = web_sys::window()
+        .and_then(|w| w.document())
+        .ok_or_else(|| ClipboardError::new("no document"))?;
+    let body = document
+        .body()
+        .ok_or_else(|| ClipboardError::new("no document body"))?;
+    let textarea: HtmlTextAreaElement = document.create_element("textarea")?.unchecked_into();
+    textarea.set_value(text);
+    // Hidden with opacity rather than `display: none`, which would prevent selecting it
+    textarea.set_attribute("style", "position: fixed; top: 0; left: 0; opacity: 0")?;
+    body.append_child(&textarea)?;
+    textarea.select();
+    let copied = document
+        .unchecked_ref::<HtmlDocument>()
+        .exec_command("copy");
+    let _ = body.remove_child(&textarea);
+    match copied? {
+        true => Ok(()),
+        false => Err(ClipboardError::new("the browser refused to copy")),
+    }
+}
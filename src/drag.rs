@@ -0,0 +1,129 @@
+//! Access to the data carried by drag and drop events.
+//!
+//! A typical reorderable list sets the index of the dragged item when the drag starts
+//! and reads it back when it is dropped:
+//!
+//! ```ignore
+//! li![
+//!     draggable(true),
+//!     on_drag_start(move |drag: DragInfo| {
+//!         drag.data.set_text(&ix.to_string());
+//!         Msg::Dragging(ix)
+//!     }),
+//!     on_drop(move |drag: DragInfo| {
+//!         let from = drag.data.text().and_then(|t| t.parse().ok());
+//!         Msg::Dropped(from, ix)
+//!     }),
+//! ]
+//! ```
+//!
+//! Note that browsers only allow the data to be read in `drop` handlers. During the
+//! rest of the drag, only the `types` of data are visible.
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::file::{self, FileInfo};
+
+const TEXT: &str = "text/plain";
+const JSON: &str = "application/json";
+
+/// The data being dragged, wrapping the DOM `DataTransfer` object
+#[derive(Clone, Debug)]
+pub struct DataTransfer(web_sys::DataTransfer);
+
+impl DataTransfer {
+    pub(crate) fn new(inner: web_sys::DataTransfer) -> DataTransfer {
+        DataTransfer(inner)
+    }
+
+    /// Set plain text data. Should be called from a `dragstart` handler
+    pub fn set_text(&self, text: &str) {
+        self.set_data(TEXT, text)
+    }
+
+    /// Get plain text data, if any. Only available in `drop` handlers
+    pub fn text(&self) -> Option<String> {
+        self.data(TEXT)
+    }
+
+    /// Set data serialized as JSON. Should be called from a `dragstart` handler
+    pub fn set_json<T: Serialize>(&self, value: &T) -> Result<(), serde_json::Error> {
+        let json = serde_json::to_string(value)?;
+        self.set_data(JSON, &json);
+        Ok(())
+    }
+
+    /// Get data set with `set_json`, if any. Only available in `drop` handlers
+    pub fn json<T: DeserializeOwned>(&self) -> Option<Result<T, serde_json::Error>> {
+        self.data(JSON).map(|json| serde_json::from_str(&json))
+    }
+
+    /// Set data of an arbitrary MIME type
+    pub fn set_data(&self, format: &str, data: &str) {
+        // Only fails if the data is read-only, i.e. outside of `dragstart`
+        if let Err(e) = self.0.set_data(format, data) {
+            error!("Failed to set drag data: {:?}", e);
+        }
+    }
+
+    /// Get data of an arbitrary MIME type, if any
+    pub fn data(&self, format: &str) -> Option<String> {
+        self.0.get_data(format).ok().filter(|data| !data.is_empty())
+    }
+
+    /// The MIME types of the data being dragged, e.g. "text/plain" or "Files"
+    pub fn types(&self) -> Vec<String> {
+        self.0
+            .types()
+            .iter()
+            .filter_map(|t| t.as_string())
+            .collect()
+    }
+
+    /// Whether data of the given type is being dragged
+    pub fn has_type(&self, format: &str) -> bool {
+        self.types().iter().any(|t| t == format)
+    }
+
+    /// The files being dragged, e.g. from the user's desktop. Only available in `drop` handlers
+    pub fn files(&self) -> Vec<FileInfo> {
+        file::file_list(self.0.files())
+    }
+
+    /// Set the kind of operation the drag will perform, which controls the cursor
+    pub fn set_drop_effect(&self, effect: DropEffect) {
+        self.0.set_drop_effect(effect.as_str())
+    }
+
+    /// Set the kind of operation allowed when dragging from this element.
+    /// Should be called from a `dragstart` handler
+    pub fn set_effect_allowed(&self, effect: DropEffect) {
+        self.0.set_effect_allowed(effect.as_str())
+    }
+
+    /// The underlying DOM object
+    pub fn as_raw(&self) -> &web_sys::DataTransfer {
+        &self.0
+    }
+}
+
+/// The operation performed by a drop
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum DropEffect {
+    /// The item may not be dropped
+    None,
+    Copy,
+    Move,
+    Link,
+}
+
+impl DropEffect {
+    fn as_str(self) -> &'static str {
+        match self {
+            DropEffect::None => "none",
+            DropEffect::Copy => "copy",
+            DropEffect::Move => "move",
+            DropEffect::Link => "link",
+        }
+    }
+}
@@ -1,167 +1,876 @@
-use std::collections::hash_map::DefaultHasher;
+use std::cell::RefCell;
 use std::fmt::{self, Debug};
-use std::hash::{Hash, Hasher};
 use std::rc::Rc;
 
-use derive_more::Display;
-use wasm_bindgen::{closure::Closure, JsCast};
-use web_sys::{Element as DomElement, Event as DomEvent, HtmlElement, KeyboardEvent};
-
+use serde::de::DeserializeOwned;
+use wasm_bindgen::{closure::Closure, JsCast, JsValue};
+use web_sys::{
+    AddEventListenerOptions, ClipboardEvent, CustomEvent, DragEvent, Element as DomElement,
+    Event as DomEvent, EventTarget, HtmlElement, HtmlFormElement, HtmlInputElement, KeyboardEvent,
+    MouseEvent, TouchEvent, TouchList, WheelEvent,
+};
+
+use crate::drag::DataTransfer;
+use crate::file::{self, FileInfo};
+use crate::form::{self, FormError};
+use crate::keyboard::{KeyName, Shortcut};
 use crate::util;
-use crate::{App, Cmd, JsResult, Model, Str};
+use crate::{Cmd, CmdInner, JsResult, Key, Model, Str};
 
-// The EventId is a subtle thing.
+// How do event handlers interact with diffing?
 //
 // For a responsive app, we want quick rendering. For
 // quick rendering, we don't want to repaint the whole DOM each time, so we use a shadow DOM.
 // We diff the shadow DOM on each change, and only re-render the nodes that have changed.
 // But what about event callbacks (onClick, etc)? How will we know if they have changed?
-// We can't 'simply' compare them (in rust) because the callbacks are closures and do not
-// impl PartialEq. We could use bare fn pointers instead, which are PartialEq, but then
-// they become rather pointless because we can't pass data from our Model. (Suppose we select
-// an option from a list, then we want the callback to send the selected option value).
+// We can't compare them because they are closures, and closures do not impl PartialEq.
 //
-// One solution is just to remove and re-add them each time, but that isn't cool.
-// Instead we make a dubious compromise. We use a Closure struct which stashes the function pointer
-// and its arguments, and hashes them all together to give our unique Id - the EventId!
-// We use this value to compare Closures and work out whether anything has changed. Is that safe,
-// or totally broken as a concept?
+// The trick is that we don't need to know! Each listener we attach to the DOM doesn't call
+// the handler directly, but looks it up in a shared cell. When diffing, we match up the events
+// of the old and new vdom by their kind (e.g. 'click'), and simply swap the new handler into
+// the cell of the existing listener. The DOM is only touched when an event is added or removed.
 //
-// When we call the closure, we simply pass the arguments into the fn pointer.
-// This unfortunately gives a horrible API, like: `on_click((), |()| Msg::Clicked)` instead of
-// just `on_click(|| Msg::Clicked)`. Is it worth it?? Maybe it can be fixed with some nice macros
-#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Display)]
-pub(crate) struct EventId(u64);
+// This means handlers can be any `Fn` closure, capturing whatever they like from the Model,
+// e.g. `on_click(move || Msg::Remove(ix))`.
+
+type Handler<M> = Rc<dyn Fn(&DomEvent) -> Option<<M as Model>::Msg>>;
 
 /// Opaque type representing a DOM event (e.g. onClick, onInput)
 pub struct Event<M: Model> {
-    id: EventId,
-    pub(crate) inner: EventInner<M>,
+    /// The DOM event type, e.g. 'click'
+    name: Str,
+    handler: Handler<M>,
+    options: ListenerOptions,
+    rate: Option<RateLimit>,
+    /// Set once the event has been attached to the DOM
+    attached: RefCell<Option<Attached<M>>>,
+}
+
+/// Options which are fixed when a listener is added to the DOM
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct ListenerOptions {
+    pub capture: bool,
+    pub passive: bool,
+    pub once: bool,
+}
+
+/// Limits how often an event delivers messages
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum RateLimit {
+    Debounce(u32),
+    Throttle(u32),
 }
 
-pub(crate) enum EventInner<M: Model> {
-    OnClick(Rc<dyn Fn() -> M::Msg>),
-    OnDblClick(Rc<dyn Fn() -> M::Msg>),
-    OnInput(Rc<dyn Fn(String) -> M::Msg>),
-    OnKeyDown(&'static str, Rc<dyn Fn() -> M::Msg>),
+/// An event which has been attached to a DOM element
+struct Attached<M: Model> {
+    // Detaches itself when dropped
+    _listener: Listener<M>,
+    // The handler currently called by the listener
+    handler: Rc<RefCell<Handler<M>>>,
 }
 
 impl<M: Model> fmt::Debug for Event<M> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self.inner {
-            EventInner::OnClick(_) => write!(f, "OnClickEvent({})", self.id),
-            EventInner::OnDblClick(_) => write!(f, "OnDblClickEvent({})", self.id),
-            EventInner::OnInput(_) => write!(f, "OnInputEvent({})", self.id),
-            EventInner::OnKeyDown(key, _) => write!(f, "OnKeyDownEvent({}, {})", key, self.id),
+        write!(f, "Event({})", self.name)
+    }
+}
+
+impl<M: Model> Event<M> {
+    fn new<S, F>(name: S, handler: F) -> Event<M>
+    where
+        S: Into<Str>,
+        F: Fn(&DomEvent) -> Option<M::Msg> + 'static,
+    {
+        Event {
+            name: name.into(),
+            handler: Rc::new(handler),
+            options: ListenerOptions::default(),
+            rate: None,
+            attached: RefCell::new(None),
         }
     }
+
+    /// Call `preventDefault` on the DOM event, e.g. to stop a form submission
+    /// from reloading the page.
+    ///
+    /// Only applied if the handler produces a message, so for example
+    /// `on_keydown("Enter", ..).prevent_default()` does not swallow other keys.
+    pub fn prevent_default(self) -> Event<M> {
+        self.on_msg(|event| event.prevent_default())
+    }
+
+    /// Call `stopPropagation` on the DOM event, so handlers on parent elements
+    /// do not fire.
+    ///
+    /// Like `prevent_default`, only applied if the handler produces a message.
+    pub fn stop_propagation(self) -> Event<M> {
+        self.on_msg(|event| event.stop_propagation())
+    }
+
+    /// Fire during the capture phase, i.e. before handlers on child elements
+    pub fn capture(mut self) -> Event<M> {
+        self.options.capture = true;
+        self
+    }
+
+    /// Promise the browser that the handler will not call `preventDefault`,
+    /// which allows smoother scrolling for touch and wheel events.
+    /// Combining this with `prevent_default` has no effect.
+    pub fn passive(mut self) -> Event<M> {
+        self.options.passive = true;
+        self
+    }
+
+    /// Remove the listener after it fires for the first time
+    pub fn once(mut self) -> Event<M> {
+        self.options.once = true;
+        self
+    }
+
+    /// Only deliver a message once the event has stopped firing for `ms` milliseconds,
+    /// e.g. to search once the user has stopped typing. Only the last message is delivered.
+    pub fn debounce(mut self, ms: u32) -> Event<M> {
+        self.rate = Some(RateLimit::Debounce(ms));
+        self
+    }
+
+    /// Deliver at most one message every `ms` milliseconds. The first message is
+    /// delivered immediately, then the most recent at the end of each interval.
+    pub fn throttle(mut self, ms: u32) -> Event<M> {
+        self.rate = Some(RateLimit::Throttle(ms));
+        self
+    }
+
+    /// Wrap the handler so that `f` runs whenever it produces a message
+    fn on_msg(mut self, f: impl Fn(&DomEvent) + 'static) -> Event<M> {
+        let handler = self.handler;
+        self.handler = Rc::new(move |event| {
+            let msg = handler(event);
+            if msg.is_some() {
+                f(event);
+            }
+            msg
+        });
+        self
+    }
+
+    /// Whether two events can share a listener
+    pub(crate) fn same_kind(&self, other: &Event<M>) -> bool {
+        self.name == other.name && self.options == other.options && self.rate == other.rate
+    }
+
+    /// Attach the event to an element. If it was previously attached elsewhere,
+    /// that listener is removed
+    pub(crate) fn attach(&self, key: Key<M>, element: &DomElement) -> JsResult<()> {
+        let handler = Rc::new(RefCell::new(self.handler.clone()));
+        let current = handler.clone();
+        let limiter = self.rate.map(|rate| Limiter::new(key, rate));
+        let inner = move |event: DomEvent| {
+            // Clone the handler out of the cell so it is not borrowed while running
+            let handler = current.borrow().clone();
+            match (handler(&event), &limiter) {
+                (Some(msg), Some(limiter)) => {
+                    limiter.push(msg);
+                    Cmd(CmdInner::NoOp)
+                }
+                (Some(msg), None) => Cmd::msg(msg),
+                (None, _) => Cmd(CmdInner::NoOp),
+            }
+        };
+        let listener = attach_event_handler_with_options(
+            key,
+            element.clone(),
+            self.name.clone(),
+            self.options,
+            inner,
+        )?;
+        *self.attached.borrow_mut() = Some(Attached {
+            _listener: listener,
+            handler,
+        });
+        Ok(())
+    }
+
+    /// Hand over our DOM listener to `new`, which has the same kind and belongs to
+    /// the same element. The listener will call the handler of `new` from now on.
+    pub(crate) fn transfer(&self, new: &Event<M>) {
+        debug_assert!(self.same_kind(new));
+        let attached = self.attached.borrow_mut().take();
+        if let Some(attached) = attached {
+            *attached.handler.borrow_mut() = new.handler.clone();
+            *new.attached.borrow_mut() = Some(attached);
+        }
+    }
+}
+
+/// Holds back messages from a debounced or throttled listener.
+///
+/// Owned by the listener closure, so when the listener is removed the limiter
+/// is dropped and any pending timeout is cleared.
+struct Limiter<M: Model> {
+    key: Key<M>,
+    rate: RateLimit,
+    state: RefCell<LimiterState<M>>,
+    // Called when the timeout expires
+    callback: RefCell<Option<Closure<dyn FnMut()>>>,
 }
 
-impl<M: Model> PartialEq for Event<M> {
-    fn eq(&self, other: &Event<M>) -> bool {
-        self.id == other.id
+struct LimiterState<M: Model> {
+    timeout: Option<i32>,
+    pending: Option<M::Msg>,
+}
+
+impl<M: Model> Limiter<M> {
+    fn new(key: Key<M>, rate: RateLimit) -> Rc<Limiter<M>> {
+        let limiter = Rc::new(Limiter {
+            key,
+            rate,
+            state: RefCell::new(LimiterState {
+                timeout: None,
+                pending: None,
+            }),
+            callback: RefCell::new(None),
+        });
+        let weak = Rc::downgrade(&limiter);
+        let callback = Closure::wrap(Box::new(move || {
+            if let Some(limiter) = weak.upgrade() {
+                limiter.expire()
+            }
+        }) as Box<dyn FnMut()>);
+        *limiter.callback.borrow_mut() = Some(callback);
+        limiter
+    }
+
+    fn push(&self, msg: M::Msg) {
+        let mut state = self.state.borrow_mut();
+        match self.rate {
+            RateLimit::Debounce(ms) => {
+                if let Some(id) = state.timeout.take() {
+                    clear_timeout(id);
+                }
+                state.pending = Some(msg);
+                state.timeout = Some(self.set_timeout(ms));
+            }
+            RateLimit::Throttle(ms) => {
+                if state.timeout.is_some() {
+                    state.pending = Some(msg);
+                } else {
+                    state.timeout = Some(self.set_timeout(ms));
+                    drop(state);
+                    self.key.dispatch(Cmd::msg(msg));
+                }
+            }
+        }
+    }
+
+    fn expire(&self) {
+        let mut state = self.state.borrow_mut();
+        state.timeout = None;
+        let msg = state.pending.take();
+        if let (Some(_), RateLimit::Throttle(ms)) = (&msg, self.rate) {
+            // Keep throttling for another interval after the trailing message
+            state.timeout = Some(self.set_timeout(ms));
+        }
+        drop(state);
+        if let Some(msg) = msg {
+            self.key.dispatch(Cmd::msg(msg));
+        }
+    }
+
+    fn set_timeout(&self, ms: u32) -> i32 {
+        let callback = self.callback.borrow();
+        let callback = callback.as_ref().expect("Limiter callback not set");
+        web_sys::window()
+            .expect("No global `window` exists")
+            .set_timeout_with_callback_and_timeout_and_arguments_0(
+                callback.as_ref().unchecked_ref(),
+                ms as i32,
+            )
+            .expect("Failed to set timeout")
     }
 }
 
-impl<M: Model> Eq for Event<M> {}
+impl<M: Model> Drop for Limiter<M> {
+    fn drop(&mut self) {
+        if let Some(id) = self.state.get_mut().timeout {
+            clear_timeout(id);
+        }
+    }
+}
 
-impl<M: Model> PartialOrd for Event<M> {
-    fn partial_cmp(&self, other: &Event<M>) -> Option<std::cmp::Ordering> {
-        Some(self.cmp(&other))
+fn clear_timeout(id: i32) {
+    if let Some(window) = web_sys::window() {
+        window.clear_timeout_with_handle(id);
     }
 }
 
-impl<M: Model> Ord for Event<M> {
-    fn cmp(&self, other: &Event<M>) -> std::cmp::Ordering {
-        self.id.cmp(&other.id)
+macro_rules! simple_events {
+    ($($(#[$doc:meta])* $func:ident => $name:expr),* $(,)?) => {
+        $(
+            $(#[$doc])*
+            pub fn $func<M: Model>(f: impl Fn() -> M::Msg + 'static) -> Event<M> {
+                Event::new($name, move |_| Some(f()))
+            }
+        )*
     }
 }
 
-impl<M: Model> Event<M> {
-    pub(crate) fn id(&self) -> EventId {
-        self.id
+// Events whose handlers take no arguments, in format <function> => <DOM event type>
+simple_events! {
+    /// Event to fire upon mouse click
+    on_click => "click",
+    /// Event to fire upon mouse double-click
+    on_dbl_click => "dblclick",
+    /// Event to fire when the element gains focus (does not bubble)
+    on_focus => "focus",
+    /// Event to fire when the element loses focus (does not bubble)
+    on_blur => "blur",
+    /// Event to fire when the element or one of its children gains focus
+    on_focus_in => "focusin",
+    /// Event to fire when the element or one of its children loses focus
+    on_focus_out => "focusout",
+    /// Event to fire when the value of an input is committed by the user
+    on_change => "change",
+    /// Event to fire when the element is scrolled
+    on_scroll => "scroll",
+    /// Event to fire when a CSS animation completes
+    on_animation_end => "animationend",
+    /// Event to fire when a CSS transition completes
+    on_transition_end => "transitionend",
+    /// Event to fire when a resource (e.g. an image) has loaded
+    on_load => "load",
+    /// Event to fire when a resource (e.g. an image) fails to load
+    on_error => "error",
+}
+
+macro_rules! typed_events {
+    ($payload:ty: $($(#[$doc:meta])* $func:ident => $name:expr),* $(,)?) => {
+        $(
+            $(#[$doc])*
+            pub fn $func<M: Model>(f: impl Fn($payload) -> M::Msg + 'static) -> Event<M> {
+                Event::new($name, move |event| Some(f(<$payload>::from_event(event))))
+            }
+        )*
     }
+}
 
-    fn click<S: Hash + 'static>(s: S, f: fn(s: &S) -> M::Msg) -> Event<M> {
-        // Can't hash fn ptr - compiler bug! Do very unsafe workaround
-        // https://github.com/rust-lang/rust/issues/46989
-        let hash = hash_closure(&s, f as usize);
-        Event {
-            id: EventId(hash),
-            inner: EventInner::OnClick(Rc::new(move || f(&s))),
+// Events whose handlers receive a typed payload, in format <function> => <DOM event type>
+typed_events! {
+    MouseInfo:
+    /// Event to fire when a mouse button is pressed over the element
+    on_mouse_down => "mousedown",
+    /// Event to fire when a mouse button is released over the element
+    on_mouse_up => "mouseup",
+    /// Event to fire when the mouse moves over the element
+    on_mouse_move => "mousemove",
+    /// Event to fire when the mouse enters the element (does not bubble)
+    on_mouse_enter => "mouseenter",
+    /// Event to fire when the mouse leaves the element (does not bubble)
+    on_mouse_leave => "mouseleave",
+    /// Event to fire when the mouse enters the element or one of its children
+    on_mouse_over => "mouseover",
+    /// Event to fire when the mouse leaves the element or one of its children
+    on_mouse_out => "mouseout",
+    /// Event to fire when the context menu is requested, e.g. by right-click
+    on_context_menu => "contextmenu",
+    /// Event to fire when a pointer becomes active over the element
+    on_pointer_down => "pointerdown",
+    /// Event to fire when a pointer is no longer active over the element
+    on_pointer_up => "pointerup",
+    /// Event to fire when a pointer moves over the element
+    on_pointer_move => "pointermove",
+    /// Event to fire when a pointer enters the element (does not bubble)
+    on_pointer_enter => "pointerenter",
+    /// Event to fire when a pointer leaves the element (does not bubble)
+    on_pointer_leave => "pointerleave",
+    /// Event to fire when a pointer enters the element or one of its children
+    on_pointer_over => "pointerover",
+    /// Event to fire when a pointer leaves the element or one of its children
+    on_pointer_out => "pointerout",
+    /// Event to fire when the browser cancels a pointer
+    on_pointer_cancel => "pointercancel",
+}
+
+typed_events! {
+    KeyInfo:
+    /// Event to fire when any key is pressed, passing its `KeyInfo`.
+    /// To respond to particular keys only, see `on_keydown`, `on_keyboard` and `on_keys`
+    on_key_info => "keydown",
+    /// Event to fire when any key is released
+    on_key_up => "keyup",
+}
+
+typed_events! {
+    WheelInfo:
+    /// Event to fire when the mouse wheel is rolled over the element
+    on_wheel => "wheel",
+}
+
+typed_events! {
+    TouchInfo:
+    /// Event to fire when a touch point is placed on the element
+    on_touch_start => "touchstart",
+    /// Event to fire when a touch point is moved along the element
+    on_touch_move => "touchmove",
+    /// Event to fire when a touch point is removed from the element
+    on_touch_end => "touchend",
+    /// Event to fire when a touch point is interrupted
+    on_touch_cancel => "touchcancel",
+}
+
+typed_events! {
+    DragInfo:
+    /// Event to fire when the user starts dragging the element. Use `DragInfo::data`
+    /// to set the data being dragged
+    on_drag_start => "dragstart",
+    /// Event to fire when a drag leaves the element
+    on_drag_leave => "dragleave",
+    /// Event to fire when a drag of the element ends, whether or not it was dropped
+    on_drag_end => "dragend",
+}
+
+/// Event to fire when a drag enters the element.
+///
+/// The default action is prevented, marking the element as a drop target.
+pub fn on_drag_enter<M: Model>(f: impl Fn(DragInfo) -> M::Msg + 'static) -> Event<M> {
+    Event::new("dragenter", move |event| {
+        Some(f(DragInfo::from_event(event)))
+    })
+    .prevent_default()
+}
+
+/// Event to fire repeatedly while a drag is over the element.
+///
+/// The default action is prevented, which is required for `on_drop` to fire.
+pub fn on_drag_over<M: Model>(f: impl Fn(DragInfo) -> M::Msg + 'static) -> Event<M> {
+    Event::new("dragover", move |event| {
+        Some(f(DragInfo::from_event(event)))
+    })
+    .prevent_default()
+}
+
+/// Mark the element as a drop target, without sending a message on every `dragover`.
+///
+/// Use this in place of `on_drag_over` when only `on_drop` is of interest.
+pub fn accept_drop<M: Model>() -> Event<M> {
+    Event::new("dragover", |event| {
+        event.prevent_default();
+        None
+    })
+}
+
+/// Event to fire when something is dropped on the element.
+///
+/// Note the element must also handle `on_drag_over` (or use `accept_drop`), otherwise
+/// the browser will not allow the drop. The default action (e.g. opening a dropped file) is prevented.
+pub fn on_drop<M: Model>(f: impl Fn(DragInfo) -> M::Msg + 'static) -> Event<M> {
+    Event::new("drop", move |event| Some(f(DragInfo::from_event(event)))).prevent_default()
+}
+
+/// Extract an event payload from the raw DOM event
+pub(crate) trait FromEvent {
+    fn from_event(event: &DomEvent) -> Self;
+}
+
+/// Which modifier keys were held down when an event fired
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Modifiers {
+    pub alt: bool,
+    pub ctrl: bool,
+    pub shift: bool,
+    pub meta: bool,
+}
+
+/// The mouse button which triggered an event
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MouseButton {
+    /// Usually the left button
+    Main,
+    /// Usually the wheel button
+    Auxiliary,
+    /// Usually the right button
+    Secondary,
+    /// Usually the 'browser back' button
+    Fourth,
+    /// Usually the 'browser forward' button
+    Fifth,
+    Other(i16),
+}
+
+impl From<i16> for MouseButton {
+    fn from(button: i16) -> Self {
+        match button {
+            0 => MouseButton::Main,
+            1 => MouseButton::Auxiliary,
+            2 => MouseButton::Secondary,
+            3 => MouseButton::Fourth,
+            4 => MouseButton::Fifth,
+            other => MouseButton::Other(other),
+        }
+    }
+}
+
+/// Payload for mouse and pointer events
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MouseInfo {
+    /// Horizontal position relative to the viewport
+    pub x: i32,
+    /// Vertical position relative to the viewport
+    pub y: i32,
+    /// Horizontal position relative to the whole document
+    pub page_x: i32,
+    /// Vertical position relative to the whole document
+    pub page_y: i32,
+    /// Horizontal position relative to the target element
+    pub offset_x: i32,
+    /// Vertical position relative to the target element
+    pub offset_y: i32,
+    /// The button which changed state (only meaningful for down/up events)
+    pub button: MouseButton,
+    /// Bitmask of all buttons currently held down (1 = main, 2 = secondary, 4 = auxiliary)
+    pub buttons: u16,
+    pub modifiers: Modifiers,
+}
+
+impl FromEvent for MouseInfo {
+    fn from_event(event: &DomEvent) -> Self {
+        let event: &MouseEvent = event.unchecked_ref();
+        MouseInfo {
+            x: event.client_x(),
+            y: event.client_y(),
+            page_x: event.page_x(),
+            page_y: event.page_y(),
+            offset_x: event.offset_x(),
+            offset_y: event.offset_y(),
+            button: event.button().into(),
+            buttons: event.buttons(),
+            modifiers: Modifiers {
+                alt: event.alt_key(),
+                ctrl: event.ctrl_key(),
+                shift: event.shift_key(),
+                meta: event.meta_key(),
+            },
         }
     }
+}
 
-    fn dbl_click<S: Hash + 'static>(s: S, f: fn(s: &S) -> M::Msg) -> Event<M> {
-        // Can't hash fn ptr - compiler bug! Do very unsafe workaround
-        // https://github.com/rust-lang/rust/issues/46989
-        let hash = hash_closure(&s, f as usize);
-        Event {
-            id: EventId(hash),
-            inner: EventInner::OnDblClick(Rc::new(move || f(&s))),
+/// Payload for keyboard events
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeyInfo {
+    /// The key value, taking into account layout and modifiers, e.g. "a", "A", "Enter"
+    pub key: String,
+    /// The key value as a typed `KeyName`, e.g. `KeyName::Enter`
+    pub name: KeyName,
+    /// The physical key, regardless of layout, e.g. "KeyA", "Enter"
+    pub code: String,
+    /// Whether the key is being held down and auto-repeating
+    pub repeat: bool,
+    pub modifiers: Modifiers,
+}
+
+impl FromEvent for KeyInfo {
+    fn from_event(event: &DomEvent) -> Self {
+        let event: &KeyboardEvent = event.unchecked_ref();
+        KeyInfo {
+            key: event.key(),
+            name: KeyName::from_key(&event.key()),
+            code: event.code(),
+            repeat: event.repeat(),
+            modifiers: Modifiers {
+                alt: event.alt_key(),
+                ctrl: event.ctrl_key(),
+                shift: event.shift_key(),
+                meta: event.meta_key(),
+            },
         }
     }
+}
 
-    pub fn keydown<S: Hash + 'static>(key: &'static str, s: S, f: fn(&S) -> M::Msg) -> Event<M> {
-        let hash = hash_closure(&s, f as usize);
-        Event {
-            id: EventId(hash),
-            inner: EventInner::OnKeyDown(key, Rc::new(move || f(&s))),
+/// The units of a wheel event's deltas
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum DeltaMode {
+    Pixel,
+    Line,
+    Page,
+}
+
+/// Payload for drag and drop events
+#[derive(Clone, Debug)]
+pub struct DragInfo {
+    /// The data being dragged
+    pub data: DataTransfer,
+    /// Position, buttons and modifiers of the mouse
+    pub mouse: MouseInfo,
+}
+
+impl FromEvent for DragInfo {
+    fn from_event(event: &DomEvent) -> Self {
+        let drag: &DragEvent = event.unchecked_ref();
+        // Only missing for synthetic events
+        let data = drag.data_transfer().unwrap_or_else(|| {
+            web_sys::DataTransfer::new().expect("Failed to create DataTransfer")
+        });
+        DragInfo {
+            data: DataTransfer::new(data),
+            mouse: MouseInfo::from_event(event),
         }
     }
+}
 
-    pub fn input<S: Hash + 'static>(s: S, f: fn(&S, String) -> M::Msg) -> Event<M> {
-        let hash = hash_closure(&s, f as usize);
-        Event {
-            id: EventId(hash),
-            inner: EventInner::OnInput(Rc::new(move |val| f(&s, val))),
+/// Payload for wheel events
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WheelInfo {
+    pub delta_x: f64,
+    pub delta_y: f64,
+    pub delta_z: f64,
+    pub delta_mode: DeltaMode,
+    /// Position, buttons and modifiers of the mouse
+    pub mouse: MouseInfo,
+}
+
+impl FromEvent for WheelInfo {
+    fn from_event(event: &DomEvent) -> Self {
+        let wheel: &WheelEvent = event.unchecked_ref();
+        WheelInfo {
+            delta_x: wheel.delta_x(),
+            delta_y: wheel.delta_y(),
+            delta_z: wheel.delta_z(),
+            delta_mode: match wheel.delta_mode() {
+                WheelEvent::DOM_DELTA_LINE => DeltaMode::Line,
+                WheelEvent::DOM_DELTA_PAGE => DeltaMode::Page,
+                _ => DeltaMode::Pixel,
+            },
+            mouse: MouseInfo::from_event(event),
         }
     }
 }
 
-fn hash_closure<S: Hash, F: Hash>(s: S, f: F) -> u64 {
-    let mut hasher = DefaultHasher::new();
-    s.hash(&mut hasher);
-    f.hash(&mut hasher);
-    hasher.finish()
+/// A single point of contact in a touch event
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TouchPoint {
+    /// Identifies the point for as long as the contact lasts
+    pub id: i32,
+    /// Horizontal position relative to the viewport
+    pub x: i32,
+    /// Vertical position relative to the viewport
+    pub y: i32,
+    /// Horizontal position relative to the whole document
+    pub page_x: i32,
+    /// Vertical position relative to the whole document
+    pub page_y: i32,
 }
 
-/// Event to fire upon mouse click
-pub fn on_click<M: Model, S: Hash + 'static>(s: S, f: fn(s: &S) -> M::Msg) -> Event<M> {
-    Event::click(s, f)
+/// Payload for touch events
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TouchInfo {
+    /// All points currently in contact with the screen
+    pub touches: Vec<TouchPoint>,
+    /// The points which changed in this event (e.g. the lifted point for `touchend`)
+    pub changed: Vec<TouchPoint>,
+    pub modifiers: Modifiers,
 }
 
-/// Event to fire upon mouse double-click
-pub fn on_dbl_click<M: Model, S: Hash + 'static>(s: S, f: fn(s: &S) -> M::Msg) -> Event<M> {
-    Event::dbl_click(s, f)
+impl FromEvent for TouchInfo {
+    fn from_event(event: &DomEvent) -> Self {
+        let event: &TouchEvent = event.unchecked_ref();
+        TouchInfo {
+            touches: touch_points(&event.touches()),
+            changed: touch_points(&event.changed_touches()),
+            modifiers: Modifiers {
+                alt: event.alt_key(),
+                ctrl: event.ctrl_key(),
+                shift: event.shift_key(),
+                meta: event.meta_key(),
+            },
+        }
+    }
 }
 
-pub fn on_keydown<M: Model, S: Hash + 'static>(
-    key: &'static str,
-    s: S,
-    f: fn(&S) -> M::Msg,
-) -> Event<M> {
-    Event::keydown(key, s, f)
+fn touch_points(list: &TouchList) -> Vec<TouchPoint> {
+    (0..list.length())
+        .filter_map(|ix| list.get(ix))
+        .map(|touch| TouchPoint {
+            id: touch.identifier(),
+            x: touch.client_x(),
+            y: touch.client_y(),
+            page_x: touch.page_x(),
+            page_y: touch.page_y(),
+        })
+        .collect()
 }
 
-pub fn on_input<M: Model, S: Hash + 'static>(s: S, f: fn(&S, String) -> M::Msg) -> Event<M> {
-    Event::input(s, f)
+/// Event to fire when the given key is pressed, e.g. "Enter"
+pub fn on_keydown<M: Model>(key: &'static str, f: impl Fn() -> M::Msg + 'static) -> Event<M> {
+    Event::new("keydown", move |event| {
+        let event: &KeyboardEvent = event.unchecked_ref();
+        if event.key() == key {
+            Some(f())
+        } else {
+            None
+        }
+    })
+}
+
+/// Event to fire when a key is pressed, if the handler returns a message.
+///
+/// This allows handling several keys with one listener, e.g.
+/// ```ignore
+/// on_keyboard(|k: KeyInfo| match k.name {
+///     KeyName::Enter => Some(Msg::Save),
+///     KeyName::Escape => Some(Msg::Cancel),
+///     _ => None,
+/// })
+/// ```
+pub fn on_keyboard<M: Model>(f: impl Fn(KeyInfo) -> Option<M::Msg> + 'static) -> Event<M> {
+    Event::new("keydown", move |event| f(KeyInfo::from_event(event)))
+}
+
+/// Event to fire when a keyboard shortcut such as "Ctrl+Enter" is pressed.
+/// See `Shortcut` for the syntax.
+///
+/// # Panics
+///
+/// Panics if the shortcut cannot be parsed.
+pub fn on_keys<M: Model>(shortcut: &str, f: impl Fn() -> M::Msg + 'static) -> Event<M> {
+    let shortcut: Shortcut = shortcut.parse().unwrap_or_else(|e| panic!("{}", e));
+    Event::new("keydown", move |event| {
+        let event: &KeyboardEvent = event.unchecked_ref();
+        let modifiers = Modifiers {
+            alt: event.alt_key(),
+            ctrl: event.ctrl_key(),
+            shift: event.shift_key(),
+            meta: event.meta_key(),
+        };
+        if shortcut.matches(&KeyName::from_key(&event.key()), modifiers) {
+            Some(f())
+        } else {
+            None
+        }
+    })
+}
+
+/// Event to fire upon input, passing the new value of the element
+pub fn on_input<M: Model>(f: impl Fn(String) -> M::Msg + 'static) -> Event<M> {
+    Event::new("input", move |event| {
+        let target: web_sys::EventTarget = event.target().expect("Missing target");
+        let target_el: &HtmlElement = target.dyn_ref().expect("Not an Html Element");
+        Some(f(
+            util::get_str_prop(target_el, "value").expect("missing value")
+        ))
+    })
+}
+
+/// Event to fire when text is pasted into the element, passing the pasted text.
+/// Pastes without any text, e.g. of an image, are ignored
+pub fn on_paste<M: Model>(f: impl Fn(String) -> M::Msg + 'static) -> Event<M> {
+    Event::new("paste", move |event| {
+        let data = event.dyn_ref::<ClipboardEvent>()?.clipboard_data()?;
+        let text = data.get_data("text/plain").ok().filter(|t| !t.is_empty())?;
+        Some(f(text))
+    })
+}
+
+/// Event to fire when files are chosen with a file input, passing the chosen files.
+/// Use `Cmd::read_file` to read their contents.
+pub fn on_files<M: Model>(f: impl Fn(Vec<FileInfo>) -> M::Msg + 'static) -> Event<M> {
+    Event::new("change", move |event| {
+        let target = event.target()?;
+        let input: &HtmlInputElement = target.dyn_ref()?;
+        Some(f(file::file_list(input.files())))
+    })
+}
+
+/// Event to fire upon a `CustomEvent` with the given name, passing its `detail`
+/// decoded with serde. Events whose detail fails to decode are logged and ignored.
+pub fn on_custom<M, T, F>(name: impl Into<Str>, f: F) -> Event<M>
+where
+    M: Model,
+    T: DeserializeOwned,
+    F: Fn(T) -> M::Msg + 'static,
+{
+    let name = name.into();
+    Event::new(name.clone(), move |event| {
+        let detail = event
+            .dyn_ref::<CustomEvent>()
+            .map(|event| event.detail())
+            .unwrap_or(JsValue::NULL);
+        // `undefined` does not stringify
+        let json = js_sys::JSON::stringify(&detail)
+            .ok()
+            .and_then(|json| json.as_string())
+            .unwrap_or_else(|| "null".into());
+        match serde_json::from_str(&json) {
+            Ok(detail) => Some(f(detail)),
+            Err(e) => {
+                error!("Failed to decode detail of '{}' event: {}", name, e);
+                None
+            }
+        }
+    })
+}
+
+/// Event to fire when a checkbox is toggled, passing whether it is now checked
+pub fn on_check<M: Model>(f: impl Fn(bool) -> M::Msg + 'static) -> Event<M> {
+    Event::new("change", move |event| {
+        let target = event.target()?;
+        let input: &HtmlInputElement = target.dyn_ref()?;
+        Some(f(input.checked()))
+    })
+}
+
+/// Event to fire when a radio button is selected, passing its value.
+///
+/// May be attached to each radio button, or to an element containing the whole group.
+pub fn on_radio<M: Model>(f: impl Fn(String) -> M::Msg + 'static) -> Event<M> {
+    Event::new("change", move |event| {
+        let target = event.target()?;
+        let input: &HtmlInputElement = target.dyn_ref()?;
+        if input.type_() == "radio" && input.checked() {
+            Some(f(input.value()))
+        } else {
+            None
+        }
+    })
+}
+
+/// Event to fire when a form is submitted, passing the contents of the form
+/// decoded into `T`. See the `form` module for how fields are decoded.
+///
+/// The default action of reloading the page is prevented.
+pub fn on_submit<M, T, F>(f: F) -> Event<M>
+where
+    M: Model,
+    T: DeserializeOwned,
+    F: Fn(Result<T, FormError>) -> M::Msg + 'static,
+{
+    Event::new("submit", move |event| {
+        let target: EventTarget = event.target().expect("Missing target");
+        let form: &HtmlFormElement = target.dyn_ref().expect("Not a form element");
+        Some(f(form::read_form(form)))
+    })
+    .prevent_default()
 }
 
 /// Represents a listener attached to the DOM.
 ///
 /// When it is dropped it will detach the corresponding listener.
 pub(crate) struct Listener<M: Model> {
-    element: DomElement,
+    target: EventTarget,
     type_: Str,
     closure: Closure<dyn FnMut(DomEvent)>,
+    // A capturing listener is a distinct listener and must be removed as such
+    capture: bool,
     marker: std::marker::PhantomData<M>,
 }
 
 impl<M: Model> Drop for Listener<M> {
     fn drop(&mut self) {
-        self.element
-            .remove_event_listener_with_callback(&self.type_, self.closure.as_ref().unchecked_ref())
+        self.target
+            .remove_event_listener_with_callback_and_bool(
+                &self.type_,
+                self.closure.as_ref().unchecked_ref(),
+                self.capture,
+            )
             .expect("failed to remove");
     }
 }
@@ -173,11 +882,17 @@ impl<M: Model> Debug for Listener<M> {
 }
 
 impl<M: Model> Listener<M> {
-    fn new(element: DomElement, type_: Str, closure: Closure<dyn FnMut(DomEvent)>) -> Listener<M> {
+    fn new(
+        target: EventTarget,
+        type_: Str,
+        closure: Closure<dyn FnMut(DomEvent)>,
+        capture: bool,
+    ) -> Listener<M> {
         Listener {
-            element,
+            target,
             type_,
             closure,
+            capture,
             marker: std::marker::PhantomData,
         }
     }
@@ -188,19 +903,18 @@ impl<M: Model> Listener<M> {
 ///
 /// The idea is to create a javascript callback that interacts with our App
 pub(crate) fn closure0<M: Model, F: FnMut() -> Cmd<M::Msg> + 'static>(
+    key: Key<M>,
     mut handler: F,
 ) -> Closure<dyn FnMut()> {
     Closure::wrap(Box::new(move || {
         web_sys::console::time();
         let cmd = handler();
-        App::<M>::with(move |app| {
-            app.loop_update(cmd).expect("Update error");
-        });
+        key.dispatch(cmd);
         web_sys::console::time_end();
     }) as Box<dyn FnMut()>)
 }
 
-pub(crate) fn closure1<M, T, F>(mut handler: F) -> Closure<dyn FnMut(T)>
+pub(crate) fn closure1<M, T, F>(key: Key<M>, mut handler: F) -> Closure<dyn FnMut(T)>
 where
     M: Model,
     F: FnMut(T) -> Cmd<M::Msg> + 'static,
@@ -209,86 +923,55 @@ where
     Closure::wrap(Box::new(move |val: T| {
         web_sys::console::time();
         let cmd = handler(val);
-        App::<M>::with(move |app| {
-            app.loop_update(cmd).expect("Update error");
-        });
+        key.dispatch(cmd);
         // TODO make the instrumentation optional/use trace logging
         web_sys::console::time_end();
     }) as Box<dyn FnMut(T)>)
 }
 
-/// Attach an event handler to an element
-pub(crate) fn attach_event_handler<M, S, F>(
-    element: DomElement,
+/// Attach an event handler to an element (or any other event target, e.g. the window)
+pub(crate) fn attach_event_handler<M, T, S, F>(
+    key: Key<M>,
+    target: T,
     event_name: S,
     handler: F,
 ) -> JsResult<Listener<M>>
 where
     M: Model,
+    T: Into<EventTarget>,
     S: Into<Str>,
     F: Fn(DomEvent) -> Cmd<M::Msg> + 'static,
 {
-    let event_name = event_name.into();
-    let cb = closure1::<M, _, _>(handler);
-    let jsfunction = cb.as_ref().unchecked_ref();
-    element.add_event_listener_with_callback(&event_name, jsfunction)?;
-    Ok(Listener::new(element, event_name, cb))
+    attach_event_handler_with_options(key, target, event_name, ListenerOptions::default(), handler)
 }
 
-fn input_handler<M: Model>(
-    element: &DomElement,
-    handler: Rc<dyn Fn(String) -> M::Msg>,
-) -> JsResult<Listener<M>> {
-    let inner = move |event: DomEvent| {
-        let target: web_sys::EventTarget = event.target().expect("Missing target");
-        let target_el: &HtmlElement = target.dyn_ref().expect("Not an Html Element");
-        Cmd::msg(handler(
-            util::get_str_prop(target_el, "value").expect("missing value"),
-        ))
-    };
-    attach_event_handler::<M, _, _>(element.clone(), "input", inner)
-}
-
-fn key_handler<M: Model>(
-    key: &'static str,
-    element: &DomElement,
-    handler: Rc<dyn Fn() -> M::Msg>,
-) -> JsResult<Listener<M>> {
-    let inner = move |event: DomEvent| {
-        let event: &KeyboardEvent = event.unchecked_ref();
-        if event.key() == key {
-            Cmd::msg(handler())
-        } else {
-            Cmd::none()
-        }
-    };
-    attach_event_handler::<M, _, _>(element.clone(), "keydown", inner)
-}
-
-fn click_handler<M: Model>(
-    element: &DomElement,
-    handler: Rc<dyn Fn() -> M::Msg>,
-) -> JsResult<Listener<M>> {
-    let inner = move |_event: DomEvent| Cmd::msg(handler());
-    attach_event_handler::<M, _, _>(element.clone(), "click", inner)
-}
-
-fn dbl_click_handler<M: Model>(
-    element: &DomElement,
-    handler: Rc<dyn Fn() -> M::Msg>,
-) -> JsResult<Listener<M>> {
-    let inner = move |_event: DomEvent| Cmd::msg(handler());
-    attach_event_handler::<M, _, _>(element.clone(), "dblclick", inner)
-}
-
-pub(crate) fn attach_event_listener<M: Model>(
-    event: &Event<M>,
-    element: &DomElement,
-) -> JsResult<Listener<M>> {
-    match &event.inner {
-        EventInner::OnClick(cb) => click_handler::<M>(&element, cb.clone()),
-        EventInner::OnDblClick(cb) => dbl_click_handler::<M>(&element, cb.clone()),
-        EventInner::OnInput(cb) => input_handler::<M>(&element, cb.clone()),
-        EventInner::OnKeyDown(key, cb) => key_handler::<M>(key, &element, cb.clone()),
-    }
+/// Attach an event handler with the given capture/passive/once options
+pub(crate) fn attach_event_handler_with_options<M, T, S, F>(
+    key: Key<M>,
+    target: T,
+    event_name: S,
+    options: ListenerOptions,
+    handler: F,
+) -> JsResult<Listener<M>>
+where
+    M: Model,
+    T: Into<EventTarget>,
+    S: Into<Str>,
+    F: Fn(DomEvent) -> Cmd<M::Msg> + 'static,
+{
+    let target = target.into();
+    let event_name = event_name.into();
+    let cb = closure1::<M, _, _>(key, handler);
+    let jsfunction = cb.as_ref().unchecked_ref();
+    let mut js_options = AddEventListenerOptions::new();
+    js_options
+        .capture(options.capture)
+        .passive(options.passive)
+        .once(options.once);
+    target.add_event_listener_with_callback_and_add_event_listener_options(
+        &event_name,
+        jsfunction,
+        &js_options,
+    )?;
+    Ok(Listener::new(target, event_name, cb, options.capture))
 }
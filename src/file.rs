@@ -0,0 +1,175 @@
+//! Reading files chosen by the user.
+//!
+//! Files are obtained from `input![type_("file")]` elements with `event::on_files`,
+//! or dropped onto the page (see `drag::DataTransfer::files`). Their contents are
+//! read asynchronously with `Cmd::read_file`:
+//!
+//! ```ignore
+//! fn update(&mut self, msg: Msg) -> Cmd<Msg> {
+//!     match msg {
+//!         Msg::Picked(files) => {
+//!             let cmds = files.iter().map(|file| {
+//!                 Cmd::read_file(file, ReadAs::Text, Msg::Loaded)
+//!             });
+//!             return Cmd::batch(cmds);
+//!         }
+//!         Msg::Loaded(Ok(FileContents::Text(text))) => ...,
+//!         Msg::Loaded(Err(e)) => error!("{}", e),
+//!         ...
+//!     }
+//! }
+//! ```
+
+use std::fmt;
+
+use futures::channel::oneshot;
+use wasm_bindgen::{closure::Closure, JsCast, JsValue};
+use web_sys::{File, FileList, FileReader};
+
+use crate::Cmd;
+
+/// A file selected by the user
+#[derive(Clone, Debug)]
+pub struct FileInfo {
+    pub name: String,
+    /// Size in bytes
+    pub size: u64,
+    /// MIME type, e.g. "text/csv". Empty if the browser could not work it out
+    pub mime: String,
+    file: File,
+}
+
+impl FileInfo {
+    /// The underlying DOM object
+    pub fn as_raw(&self) -> &File {
+        &self.file
+    }
+}
+
+impl From<File> for FileInfo {
+    fn from(file: File) -> FileInfo {
+        FileInfo {
+            name: file.name(),
+            size: file.size() as u64,
+            mime: file.type_(),
+            file,
+        }
+    }
+}
+
+pub(crate) fn file_list(files: Option<FileList>) -> Vec<FileInfo> {
+    match files {
+        Some(files) => (0..files.length())
+            .filter_map(|ix| files.get(ix))
+            .map(FileInfo::from)
+            .collect(),
+        None => Vec::new(),
+    }
+}
+
+/// How to read a file's contents
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ReadAs {
+    /// Decode the file as UTF-8 text
+    Text,
+    /// Read the raw bytes
+    Bytes,
+    /// Encode the file as a `data:` URL, e.g. to display a chosen image
+    DataUrl,
+}
+
+/// The contents of a file, in the form requested by `ReadAs`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FileContents {
+    Text(String),
+    Bytes(Vec<u8>),
+    DataUrl(String),
+}
+
+/// An error reading a file
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FileError {
+    /// The name of the file
+    pub name: String,
+    pub message: String,
+}
+
+impl fmt::Display for FileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "failed to read '{}': {}", self.name, self.message)
+    }
+}
+
+impl std::error::Error for FileError {}
+
+impl<Msg: 'static> Cmd<Msg> {
+    /// Read the contents of a file, passing the result to `update`
+    pub fn read_file(
+        file: &FileInfo,
+        read_as: ReadAs,
+        to_msg: impl FnOnce(Result<FileContents, FileError>) -> Msg + 'static,
+    ) -> Cmd<Msg> {
+        let file = file.clone();
+        Cmd::spawn(async move { Cmd::msg(to_msg(read_file(&file, read_as).await)) })
+    }
+}
+
+/// Removes the handler and aborts the read if dropped early, e.g. when the app is unmounted
+struct ReaderGuard {
+    reader: FileReader,
+    _onloadend: Closure<dyn FnMut()>,
+}
+
+impl Drop for ReaderGuard {
+    fn drop(&mut self) {
+        self.reader.set_onloadend(None);
+        if self.reader.ready_state() == FileReader::LOADING {
+            self.reader.abort();
+        }
+    }
+}
+
+async fn read_file(file: &FileInfo, read_as: ReadAs) -> Result<FileContents, FileError> {
+    let fail = |e: JsValue| FileError {
+        name: file.name.clone(),
+        message: match e.dyn_ref::<js_sys::Error>() {
+            Some(e) => String::from(e.message()),
+            None => format!("{:?}", e),
+        },
+    };
+    let reader = FileReader::new().map_err(fail)?;
+    let (tx, rx) = oneshot::channel();
+    let mut tx = Some(tx);
+    let onloadend = Closure::wrap(Box::new(move || {
+        if let Some(tx) = tx.take() {
+            let _ = tx.send(());
+        }
+    }) as Box<dyn FnMut()>);
+    reader.set_onloadend(Some(onloadend.as_ref().unchecked_ref()));
+    let guard = ReaderGuard {
+        reader,
+        _onloadend: onloadend,
+    };
+    let reader = &guard.reader;
+    match read_as {
+        ReadAs::Text => reader.read_as_text(&file.file),
+        ReadAs::Bytes => reader.read_as_array_buffer(&file.file),
+        ReadAs::DataUrl => reader.read_as_data_url(&file.file),
+    }
+    .map_err(fail)?;
+
+    let _ = rx.await;
+    if let Some(e) = reader.error() {
+        return Err(FileError {
+            name: file.name.clone(),
+            message: e.message(),
+        });
+    }
+    let result = reader.result().map_err(fail)?;
+    let contents = match read_as {
+        ReadAs::Text => FileContents::Text(result.as_string().unwrap_or_default()),
+        ReadAs::DataUrl => FileContents::DataUrl(result.as_string().unwrap_or_default()),
+        ReadAs::Bytes => FileContents::Bytes(js_sys::Uint8Array::new(&result).to_vec()),
+    };
+    Ok(contents)
+}
@@ -0,0 +1,380 @@
+//! Decoding HTML form contents into Rust types.
+//!
+//! `event::on_submit` collects the `FormData` of a submitted form and decodes it
+//! into any type implementing `serde::Deserialize`, keyed by the `name` of each field:
+//!
+//! ```ignore
+//! #[derive(Deserialize)]
+//! struct Login {
+//!     username: String,
+//!     age: Option<u32>,   // empty field -> None
+//!     #[serde(default)]
+//!     remember: bool,     // unchecked checkboxes are not submitted
+//!     tags: Vec<String>,  // fields with the same name are collected
+//! }
+//!
+//! form![
+//!     on_submit(|res: Result<Login, FormError>| Msg::Login(res)),
+//!     input![name("username")],
+//!     ...
+//! ]
+//! ```
+//!
+//! Values are always strings in the form, so they are parsed into numbers, bools
+//! etc as requested by the target type. Nested structs and maps are not supported.
+//! File inputs are skipped.
+
+use std::fmt;
+
+use serde::de::{self, DeserializeOwned, IntoDeserializer, Visitor};
+use serde::forward_to_deserialize_any;
+use wasm_bindgen::JsCast;
+use web_sys::{FormData, HtmlFormElement};
+
+/// An error decoding a form
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FormError {
+    /// The name of the field which failed to decode, if the error relates to a field
+    pub field: Option<String>,
+    pub message: String,
+}
+
+impl FormError {
+    fn in_field(mut self, field: &str) -> FormError {
+        if self.field.is_none() {
+            self.field = Some(field.to_string());
+        }
+        self
+    }
+}
+
+impl fmt::Display for FormError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.field {
+            Some(field) => write!(f, "field '{}': {}", field, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl std::error::Error for FormError {}
+
+impl de::Error for FormError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        FormError {
+            field: None,
+            message: msg.to_string(),
+        }
+    }
+
+    fn missing_field(field: &'static str) -> Self {
+        FormError {
+            field: Some(field.to_string()),
+            message: "missing field".into(),
+        }
+    }
+}
+
+/// Read the fields of a form element and decode them
+pub(crate) fn read_form<T: DeserializeOwned>(form: &HtmlFormElement) -> Result<T, FormError> {
+    let js_err = |e: wasm_bindgen::JsValue| FormError {
+        field: None,
+        message: format!("failed to read form: {:?}", e),
+    };
+    let data = FormData::new_with_form(form).map_err(js_err)?;
+    let entries = js_sys::try_iter(&data)
+        .map_err(js_err)?
+        .ok_or_else(|| js_err("FormData is not iterable".into()))?;
+    let mut pairs = Vec::new();
+    for entry in entries {
+        let entry: js_sys::Array = entry.map_err(js_err)?.unchecked_into();
+        // Files have non-string values
+        if let (Some(name), Some(value)) = (entry.get(0).as_string(), entry.get(1).as_string()) {
+            pairs.push((name, value));
+        }
+    }
+    from_pairs(pairs)
+}
+
+/// Decode a list of (name, value) pairs, as submitted by a form
+pub fn from_pairs<T: DeserializeOwned>(pairs: Vec<(String, String)>) -> Result<T, FormError> {
+    // Group repeated names, preserving the order in which they first appear
+    let mut fields: Vec<(String, Vec<String>)> = Vec::new();
+    for (name, value) in pairs {
+        match fields.iter_mut().find(|(n, _)| *n == name) {
+            Some((_, values)) => values.push(value),
+            None => fields.push((name, vec![value])),
+        }
+    }
+    T::deserialize(FormDeserializer { fields })
+}
+
+struct FormDeserializer {
+    fields: Vec<(String, Vec<String>)>,
+}
+
+impl<'de> de::Deserializer<'de> for FormDeserializer {
+    type Error = FormError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, FormError> {
+        visitor.visit_map(FieldsAccess {
+            fields: self.fields.into_iter(),
+            current: None,
+        })
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+struct FieldsAccess {
+    fields: std::vec::IntoIter<(String, Vec<String>)>,
+    current: Option<(String, Vec<String>)>,
+}
+
+impl<'de> de::MapAccess<'de> for FieldsAccess {
+    type Error = FormError;
+
+    fn next_key_seed<K: de::DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, FormError> {
+        match self.fields.next() {
+            Some((name, values)) => {
+                let key = seed.deserialize(name.as_str().into_deserializer())?;
+                self.current = Some((name, values));
+                Ok(Some(key))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: de::DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, FormError> {
+        let (name, values) = self.current.take().expect("value requested before key");
+        seed.deserialize(ValueDeserializer { values })
+            .map_err(|e| e.in_field(&name))
+    }
+}
+
+/// Deserializes the value(s) submitted for a single field name
+struct ValueDeserializer {
+    values: Vec<String>,
+}
+
+impl ValueDeserializer {
+    /// Where a single value is expected, the last one submitted wins
+    fn single(mut self) -> String {
+        self.values.pop().unwrap_or_default()
+    }
+}
+
+macro_rules! deserialize_parsed {
+    ($($method:ident => $visit:ident),* $(,)?) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, FormError> {
+                let value = self.single();
+                match value.trim().parse() {
+                    Ok(parsed) => visitor.$visit(parsed),
+                    Err(e) => Err(de::Error::custom(format!("invalid value {:?}: {}", value, e))),
+                }
+            }
+        )*
+    }
+}
+
+impl<'de> de::Deserializer<'de> for ValueDeserializer {
+    type Error = FormError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, FormError> {
+        if self.values.len() > 1 {
+            self.deserialize_seq(visitor)
+        } else {
+            visitor.visit_string(self.single())
+        }
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, FormError> {
+        let value = self.single();
+        match value.trim().to_lowercase().as_str() {
+            // Checkboxes submit "on" unless they have a value attribute
+            "true" | "on" | "yes" | "1" => visitor.visit_bool(true),
+            "false" | "off" | "no" | "0" | "" => visitor.visit_bool(false),
+            _ => Err(de::Error::custom(format!("invalid bool {:?}", value))),
+        }
+    }
+
+    deserialize_parsed! {
+        deserialize_i8 => visit_i8,
+        deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32,
+        deserialize_i64 => visit_i64,
+        deserialize_u8 => visit_u8,
+        deserialize_u16 => visit_u16,
+        deserialize_u32 => visit_u32,
+        deserialize_u64 => visit_u64,
+        deserialize_f32 => visit_f32,
+        deserialize_f64 => visit_f64,
+        deserialize_char => visit_char,
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, FormError> {
+        // An empty text input submits an empty string
+        if self.values.iter().all(|v| v.is_empty()) {
+            visitor.visit_none()
+        } else {
+            visitor.visit_some(self)
+        }
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, FormError> {
+        let values = self.values.into_iter().map(|value| ValueDeserializer {
+            values: vec![value],
+        });
+        visitor.visit_seq(de::value::SeqDeserializer::new(values))
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, FormError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, FormError> {
+        // Only unit variants can be represented, e.g. the options of a select
+        let value: de::value::StringDeserializer<FormError> = self.single().into_deserializer();
+        value.deserialize_enum(name, variants, visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, FormError> {
+        Err(de::Error::custom("nested values are not supported"))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _fields: &'static [&'static str],
+        _visitor: V,
+    ) -> Result<V::Value, FormError> {
+        Err(de::Error::custom("nested values are not supported"))
+    }
+
+    forward_to_deserialize_any! {
+        i128 u128 str string bytes byte_buf unit unit_struct tuple
+        tuple_struct identifier ignored_any
+    }
+}
+
+impl<'de> IntoDeserializer<'de, FormError> for ValueDeserializer {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    fn pairs(items: &[(&str, &str)]) -> Vec<(String, String)> {
+        items
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_decode_form() {
+        let form = pairs(&[("name", "Jess"), ("colour", "blue")]);
+        let decoded: BTreeMap<String, String> = from_pairs(form).unwrap();
+        assert_eq!(decoded["name"], "Jess");
+        assert_eq!(decoded["colour"], "blue");
+
+        let form = pairs(&[("age", "30"), ("height", "")]);
+        let decoded: BTreeMap<String, Option<u32>> = from_pairs(form).unwrap();
+        assert_eq!(decoded["age"], Some(30));
+        assert_eq!(decoded["height"], None);
+
+        let form = pairs(&[("subscribe", "on"), ("tags", "a"), ("tags", "b")]);
+        let decoded: BTreeMap<String, Vec<String>> = from_pairs(form).unwrap();
+        assert_eq!(decoded["tags"], vec!["a".to_string(), "b".to_string()]);
+        assert_eq!(decoded["subscribe"], vec!["on".to_string()]);
+
+        let form = pairs(&[("subscribe", "on"), ("spam", "")]);
+        let decoded: BTreeMap<String, bool> = from_pairs(form).unwrap();
+        assert!(decoded["subscribe"]);
+        assert!(!decoded["spam"]);
+    }
+
+    #[test]
+    fn test_field_errors() {
+        let form = pairs(&[("age", "30"), ("height", "tall")]);
+        let err = from_pairs::<BTreeMap<String, u32>>(form).unwrap_err();
+        assert_eq!(err.field.as_deref(), Some("height"));
+        assert_eq!(
+            err.to_string(),
+            "field 'height': invalid value \"tall\": invalid digit found in string"
+        );
+
+        let form = pairs(&[("subscribe", "maybe")]);
+        let err = from_pairs::<BTreeMap<String, bool>>(form).unwrap_err();
+        assert_eq!(err.field.as_deref(), Some("subscribe"));
+    }
+
+    #[derive(Debug, PartialEq, serde::Deserialize)]
+    struct Signup {
+        username: String,
+        age: u32,
+        nickname: Option<String>,
+        #[serde(default)]
+        tags: Vec<String>,
+    }
+
+    #[test]
+    fn test_decode_struct() {
+        let form = pairs(&[
+            ("username", "jess"),
+            ("age", "30"),
+            ("tags", "a"),
+            ("tags", "b"),
+        ]);
+        let decoded: Signup = from_pairs(form).unwrap();
+        assert_eq!(
+            decoded,
+            Signup {
+                username: "jess".into(),
+                age: 30,
+                nickname: None,
+                tags: vec!["a".into(), "b".into()],
+            }
+        );
+
+        let form = pairs(&[("username", "jess"), ("age", "thirty")]);
+        let err = from_pairs::<Signup>(form).unwrap_err();
+        assert_eq!(err.field.as_deref(), Some("age"));
+
+        let form = pairs(&[("username", "jess")]);
+        let err = from_pairs::<Signup>(form).unwrap_err();
+        assert_eq!(
+            err,
+            FormError {
+                field: Some("age".into()),
+                message: "missing field".into(),
+            }
+        );
+    }
+}
@@ -1,6 +1,10 @@
 use crate::{Element, Html, Model, Str};
 use derive_more::Constructor;
+use std::cell::RefCell;
 use std::collections::BTreeMap;
+use std::fmt;
+use std::rc::Rc;
+use web_sys::Element as DomElement;
 
 use crate::event::Event;
 
@@ -66,6 +70,7 @@ make_html_tags! {
     Div => div,
     Em => em,
     Footer => footer,
+    Form => form,
     H1 => h1,
     H2 => h2,
     H3 => h3,
@@ -176,11 +181,14 @@ macro_rules! attr_def {
 
 // Generate Attribute variants, along with their contents and key name
 attr_def! {
+    Checked => () => checked,
     Class => Vec<Str> => class,
     Disabled => () => disabled,
+    Draggable => bool => draggable,
     For => Str => for,
     Href => Str => href,
     Id => Str => id,
+    Name => Str => name,
     Placeholder => Str => placeholder,
     Selected => () => selected,
     Style => Style => style,
@@ -197,11 +205,12 @@ impl Attribute {
         use AttributeInner::*;
         match &self.0 {
             Class(classes) => classes.join(" ").into(),
+            Checked(()) => "checked".into(),
             Disabled(()) => "disabled".into(),
+            Draggable(draggable) => draggable.to_string().into(),
             Selected(()) => "selected".into(),
-            For(val) | Href(val) | Id(val) | Placeholder(val) | Value(val) | Type(val) => {
-                val.clone()
-            }
+            For(val) | Href(val) | Id(val) | Name(val) | Placeholder(val) | Value(val)
+            | Type(val) => val.clone(),
             Style(style) => style.to_string().into(),
         }
     }
@@ -228,6 +237,7 @@ macro_rules! attr_key_value_func {
 attr_key_value_func!(for_, For);
 attr_key_value_func!(href, Href);
 attr_key_value_func!(id, Id);
+attr_key_value_func!(name, Name);
 attr_key_value_func!(placeholder, Placeholder);
 attr_key_value_func!(type_, Type);
 attr_key_value_func!(value, Value);
@@ -253,11 +263,21 @@ pub fn selected() -> Attribute {
     Attribute(AttributeInner::Selected(()))
 }
 
+/// Add the `checked` attribute to an element
+pub fn checked() -> Attribute {
+    Attribute(AttributeInner::Checked(()))
+}
+
 /// Add the `disabled` attribute to an element
 pub fn disabled() -> Attribute {
     Attribute(AttributeInner::Disabled(()))
 }
 
+/// Set whether the element can be dragged
+pub fn draggable(draggable: bool) -> Attribute {
+    Attribute(AttributeInner::Draggable(draggable))
+}
+
 #[doc(hidden)]
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Constructor)]
 pub struct Style(BTreeMap<String, String>);
@@ -294,6 +314,54 @@ macro_rules! style {
     }
 }
 
+/// A reference to a rendered DOM element, for commands which act on the DOM
+/// such as `Cmd::dispatch_event`.
+///
+/// Keep a `NodeRef` in the model and pass a clone of it to the element in `view`:
+/// ```rust,ignore
+/// div![self.widget.clone(), ...]
+/// ```
+#[derive(Clone, Default)]
+pub struct NodeRef(Rc<RefCell<Option<DomElement>>>);
+
+impl NodeRef {
+    pub fn new() -> NodeRef {
+        NodeRef::default()
+    }
+
+    /// The referenced element, if it is currently rendered
+    pub fn get(&self) -> Option<DomElement> {
+        self.0.borrow().clone().filter(|el| el.is_connected())
+    }
+
+    pub(crate) fn set(&self, element: Option<DomElement>) {
+        *self.0.borrow_mut() = element;
+    }
+
+    /// Clear the ref, unless it has since been pointed at another element
+    pub(crate) fn release(&self, element: &DomElement) {
+        release(&self.0, element)
+    }
+
+    pub(crate) fn ptr_eq(&self, other: &NodeRef) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+/// Empty the slot if it holds `element`
+pub(crate) fn release<E: PartialEq>(slot: &RefCell<Option<E>>, element: &E) {
+    let mut slot = slot.borrow_mut();
+    if slot.as_ref() == Some(element) {
+        *slot = None;
+    }
+}
+
+impl fmt::Debug for NodeRef {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "NodeRef")
+    }
+}
+
 /// Trait that allows that type to be visted by an Element
 pub trait AcceptParent<M: Model> {
     fn accept_parent_element(self, elem: &mut Element<M>);
@@ -338,6 +406,12 @@ impl<M: Model> AcceptParent<M> for Event<M> {
     }
 }
 
+impl<M: Model> AcceptParent<M> for NodeRef {
+    fn accept_parent_element(self, elem: &mut Element<M>) {
+        elem.node_ref = Some(self)
+    }
+}
+
 impl<M: Model> AcceptParent<M> for Html<M> {
     fn accept_parent_element(self, elem: &mut Element<M>) {
         elem.children.push(self)
@@ -0,0 +1,547 @@
+//! Typed HTTP requests, built on the browser's `fetch`.
+//!
+//! The simplest requests are made with `Cmd::get_json` and `Cmd::post_json`, which
+//! decode the response body as JSON and pass the result to `update`:
+//!
+//! ```ignore
+//! fn update(&mut self, msg: Msg) -> Cmd<Msg> {
+//!     match msg {
+//!         Msg::Load => return Cmd::get_json("/api/items", Msg::Loaded),
+//!         Msg::Loaded(Ok(items)) => self.items = items,
+//!         Msg::Loaded(Err(HttpError::Status { status: 404, .. })) => self.items.clear(),
+//!         Msg::Loaded(Err(e)) => error!("{}", e),
+//!         ...
+//!     }
+//! }
+//! ```
+//!
+//! For custom headers, methods or timeouts, build a `Request` and send it with
+//! `Cmd::fetch_json` or `Cmd::fetch`. Responses with a non-2xx status are errors.
+//!
+//! To show the progress of a large upload or download, use `Cmd::fetch_with_progress`:
+//!
+//! ```ignore
+//! Msg::Upload(file) => {
+//!     let request = Request::post("/api/upload").file(&file);
+//!     return Cmd::fetch_with_progress(request, Msg::Uploading);
+//! }
+//! Msg::Uploading(Transfer::Upload(progress)) => self.progress = progress.fraction(),
+//! Msg::Uploading(Transfer::Done(res)) => ...,
+//! ```
+
+use std::fmt;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use futures::channel::mpsc;
+use futures::future::{self, Either};
+use futures::{Stream, StreamExt};
+use serde::{de::DeserializeOwned, Serialize};
+use wasm_bindgen::{closure::Closure, JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{Headers, ProgressEvent, RequestInit, XmlHttpRequest};
+
+use crate::file::FileInfo;
+use crate::timer;
+use crate::util::AbortOnDrop;
+use crate::Cmd;
+
+/// An error making an HTTP request
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum HttpError {
+    /// The request could not be made, e.g. the network is down or it was blocked by CORS
+    Network(String),
+    /// No response arrived within the timeout set with `Request::timeout`
+    Timeout,
+    /// The server responded with a non-2xx status code
+    Status { status: u16, body: String },
+    /// The request body could not be serialized
+    Encode(String),
+    /// The response body could not be decoded
+    Decode(String),
+}
+
+impl HttpError {
+    fn network(e: JsValue) -> HttpError {
+        match e.dyn_ref::<js_sys::Error>() {
+            Some(e) => HttpError::Network(String::from(e.message())),
+            None => HttpError::Network(format!("{:?}", e)),
+        }
+    }
+}
+
+impl fmt::Display for HttpError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HttpError::Network(msg) => write!(f, "network error: {}", msg),
+            HttpError::Timeout => write!(f, "request timed out"),
+            HttpError::Status { status, .. } => write!(f, "server responded with {}", status),
+            HttpError::Encode(msg) => write!(f, "failed to encode request: {}", msg),
+            HttpError::Decode(msg) => write!(f, "failed to decode response: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for HttpError {}
+
+#[derive(Clone, Debug)]
+enum Body {
+    Text(String),
+    File(FileInfo),
+}
+
+impl Body {
+    fn to_js(&self) -> JsValue {
+        match self {
+            Body::Text(text) => JsValue::from_str(text),
+            Body::File(file) => file.as_raw().into(),
+        }
+    }
+}
+
+/// An HTTP request, built up with the methods below
+#[derive(Clone, Debug)]
+pub struct Request {
+    method: String,
+    url: String,
+    headers: Vec<(String, String)>,
+    body: Option<Body>,
+    timeout: Option<Duration>,
+    /// Set if the body failed to serialize, and returned when the request is sent
+    error: Option<HttpError>,
+}
+
+impl Request {
+    /// A request with the given method, e.g. "PATCH"
+    pub fn new(method: &str, url: &str) -> Request {
+        Request {
+            method: method.to_string(),
+            url: url.to_string(),
+            headers: Vec::new(),
+            body: None,
+            timeout: None,
+            error: None,
+        }
+    }
+
+    pub fn get(url: &str) -> Request {
+        Request::new("GET", url)
+    }
+
+    pub fn post(url: &str) -> Request {
+        Request::new("POST", url)
+    }
+
+    pub fn put(url: &str) -> Request {
+        Request::new("PUT", url)
+    }
+
+    pub fn delete(url: &str) -> Request {
+        Request::new("DELETE", url)
+    }
+
+    /// Add a header, e.g. `.header("Authorization", &format!("Bearer {}", token))`
+    pub fn header(mut self, name: &str, value: &str) -> Request {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Set the body of the request
+    pub fn body(mut self, body: impl Into<String>) -> Request {
+        self.body = Some(Body::Text(body.into()));
+        self
+    }
+
+    /// Send the contents of a file chosen by the user as the body of the request
+    pub fn file(mut self, file: &FileInfo) -> Request {
+        if !file.mime.is_empty() {
+            self = self.header("Content-Type", &file.mime);
+        }
+        self.body = Some(Body::File(file.clone()));
+        self
+    }
+
+    /// Set the body of the request to a value serialized as JSON
+    pub fn json<T: Serialize>(mut self, value: &T) -> Request {
+        match serde_json::to_string(value) {
+            Ok(json) => self.body = Some(Body::Text(json)),
+            Err(e) => self.error = Some(HttpError::Encode(e.to_string())),
+        }
+        self.header("Content-Type", "application/json")
+    }
+
+    /// Give up on the request if no response has arrived after the given duration
+    pub fn timeout(mut self, duration: Duration) -> Request {
+        self.timeout = Some(duration);
+        self
+    }
+
+    /// Send the request, e.g. within a future passed to `Cmd::spawn`.
+    ///
+    /// Dropping the future aborts the request.
+    pub async fn send(self) -> Result<Response, HttpError> {
+        let timeout = self.timeout;
+        let fetch = Box::pin(self.fetch());
+        match timeout {
+            None => fetch.await,
+            Some(duration) => match future::select(fetch, timer::sleep(duration)).await {
+                Either::Left((res, _)) => res,
+                // Dropping the fetch aborts it
+                Either::Right(_) => Err(HttpError::Timeout),
+            },
+        }
+    }
+
+    /// Send the request and decode the response as JSON
+    pub async fn send_json<T: DeserializeOwned>(self) -> Result<T, HttpError> {
+        self.send().await?.json()
+    }
+
+    /// Send the request, reporting the progress of the upload and download before the
+    /// final result. The stream ends after the `Transfer::Done` item.
+    ///
+    /// The request is sent when the stream is first polled, and dropping the stream
+    /// aborts it.
+    pub fn send_with_progress(self) -> impl Stream<Item = Transfer> {
+        TransferStream::Unsent(self)
+    }
+
+    /// Send the request with an `XMLHttpRequest`, as `fetch` cannot report upload progress
+    fn xhr(self, tx: mpsc::UnboundedSender<Transfer>) -> Result<XhrGuard, JsValue> {
+        let xhr = XmlHttpRequest::new()?;
+        xhr.open_with_async(&self.method, &self.url, true)?;
+        for (name, value) in &self.headers {
+            xhr.set_request_header(name, value)?;
+        }
+        if let Some(timeout) = self.timeout {
+            xhr.set_timeout(timeout.as_millis().min(u32::MAX as u128) as u32);
+        }
+
+        let handler = |tx: &mpsc::UnboundedSender<Transfer>,
+                       to_transfer: Box<dyn Fn(&ProgressEvent) -> Transfer>| {
+            let tx = tx.clone();
+            Closure::wrap(Box::new(move |ev: ProgressEvent| {
+                let _ = tx.unbounded_send(to_transfer(&ev));
+            }) as Box<dyn FnMut(ProgressEvent)>)
+        };
+        let on_download = handler(&tx, Box::new(|ev| Transfer::Download(Progress::from(ev))));
+        let on_load = {
+            let xhr = xhr.clone();
+            handler(&tx, Box::new(move |_| Transfer::Done(xhr_response(&xhr))))
+        };
+        let on_error = handler(
+            &tx,
+            Box::new(|_| Transfer::Done(Err(HttpError::Network("request failed".into())))),
+        );
+        let on_timeout = handler(&tx, Box::new(|_| Transfer::Done(Err(HttpError::Timeout))));
+        xhr.set_onprogress(Some(on_download.as_ref().unchecked_ref()));
+        xhr.set_onload(Some(on_load.as_ref().unchecked_ref()));
+        xhr.set_onerror(Some(on_error.as_ref().unchecked_ref()));
+        xhr.set_ontimeout(Some(on_timeout.as_ref().unchecked_ref()));
+        let mut handlers = vec![on_download, on_load, on_error, on_timeout];
+        // Only listen for upload progress if there is something to upload, as the
+        // listener forces a CORS preflight request
+        if self.body.is_some() {
+            let on_upload = handler(&tx, Box::new(|ev| Transfer::Upload(Progress::from(ev))));
+            xhr.upload()?
+                .set_onprogress(Some(on_upload.as_ref().unchecked_ref()));
+            handlers.push(on_upload);
+        }
+
+        match &self.body {
+            None => xhr.send(),
+            Some(Body::Text(text)) => xhr.send_with_opt_str(Some(text)),
+            Some(Body::File(file)) => {
+                let blob: &web_sys::Blob = file.as_raw();
+                xhr.send_with_opt_blob(Some(blob))
+            }
+        }?;
+        Ok(XhrGuard {
+            xhr,
+            _handlers: handlers,
+        })
+    }
+
+    async fn fetch(self) -> Result<Response, HttpError> {
+        if let Some(e) = self.error {
+            return Err(e);
+        }
+        let abort = AbortOnDrop::new().map_err(HttpError::network)?;
+        let mut init = RequestInit::new();
+        init.method(&self.method);
+        init.signal(Some(&abort.signal()));
+        if let Some(body) = &self.body {
+            init.body(Some(&body.to_js()));
+        }
+        let request = web_sys::Request::new_with_str_and_init(&self.url, &init)
+            .map_err(HttpError::network)?;
+        for (name, value) in &self.headers {
+            request
+                .headers()
+                .set(name, value)
+                .map_err(HttpError::network)?;
+        }
+
+        let window = web_sys::window().expect("No global `window` exists");
+        let response: web_sys::Response = JsFuture::from(window.fetch_with_request(&request))
+            .await
+            .map_err(HttpError::network)?
+            .unchecked_into();
+        let text = response.text().map_err(HttpError::network)?;
+        let body = JsFuture::from(text)
+            .await
+            .map_err(HttpError::network)?
+            .as_string()
+            .unwrap_or_default();
+        let status = response.status();
+        if !response.ok() {
+            return Err(HttpError::Status { status, body });
+        }
+        Ok(Response {
+            status,
+            body,
+            headers: response.headers(),
+        })
+    }
+}
+
+/// The progress of an upload or download
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Progress {
+    /// The number of bytes transferred so far
+    pub loaded: u64,
+    /// The total number of bytes, if known
+    pub total: Option<u64>,
+}
+
+impl Progress {
+    /// The fraction of the transfer completed, between 0 and 1, if the total is known
+    pub fn fraction(&self) -> Option<f64> {
+        match self.total {
+            Some(0) => Some(1.),
+            Some(total) => Some(self.loaded as f64 / total as f64),
+            None => None,
+        }
+    }
+}
+
+impl From<&ProgressEvent> for Progress {
+    fn from(ev: &ProgressEvent) -> Progress {
+        Progress {
+            loaded: ev.loaded() as u64,
+            total: if ev.length_computable() {
+                Some(ev.total() as u64)
+            } else {
+                None
+            },
+        }
+    }
+}
+
+/// An update on a request sent with `Cmd::fetch_with_progress`
+#[derive(Clone, Debug)]
+pub enum Transfer {
+    /// Part of the request body has been sent
+    Upload(Progress),
+    /// Part of the response body has been received
+    Download(Progress),
+    /// The request has finished. Always the last update
+    Done(Result<Response, HttpError>),
+}
+
+/// The stream returned by `Request::send_with_progress`
+enum TransferStream {
+    /// Not yet polled
+    Unsent(Request),
+    Sending {
+        rx: mpsc::UnboundedReceiver<Transfer>,
+        xhr: XhrGuard,
+    },
+    Finished,
+}
+
+impl Stream for TransferStream {
+    type Item = Transfer;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Transfer>> {
+        let this = self.get_mut();
+        loop {
+            match std::mem::replace(this, TransferStream::Finished) {
+                TransferStream::Unsent(mut request) => {
+                    let (tx, rx) = mpsc::unbounded();
+                    let xhr = match request.error.take() {
+                        None => request.xhr(tx).map_err(HttpError::network),
+                        Some(e) => Err(e),
+                    };
+                    match xhr {
+                        Ok(xhr) => *this = TransferStream::Sending { rx, xhr },
+                        Err(e) => return Poll::Ready(Some(Transfer::Done(Err(e)))),
+                    }
+                }
+                TransferStream::Sending { mut rx, xhr } => {
+                    let next = rx.poll_next_unpin(cx);
+                    match next {
+                        // The request has finished, so drop the xhr
+                        Poll::Ready(None) | Poll::Ready(Some(Transfer::Done(_))) => {}
+                        _ => *this = TransferStream::Sending { rx, xhr },
+                    }
+                    return next;
+                }
+                TransferStream::Finished => return Poll::Ready(None),
+            }
+        }
+    }
+}
+
+/// Keeps the handlers of an `XMLHttpRequest` alive. Removes them and aborts the
+/// request if dropped before it has finished
+struct XhrGuard {
+    xhr: XmlHttpRequest,
+    _handlers: Vec<Closure<dyn FnMut(ProgressEvent)>>,
+}
+
+impl Drop for XhrGuard {
+    fn drop(&mut self) {
+        let xhr = &self.xhr;
+        xhr.set_onprogress(None);
+        xhr.set_onload(None);
+        xhr.set_onerror(None);
+        xhr.set_ontimeout(None);
+        if let Ok(upload) = xhr.upload() {
+            upload.set_onprogress(None);
+        }
+        if xhr.ready_state() != XmlHttpRequest::DONE {
+            let _ = xhr.abort();
+        }
+    }
+}
+
+fn xhr_response(xhr: &XmlHttpRequest) -> Result<Response, HttpError> {
+    let status = xhr.status().map_err(HttpError::network)?;
+    let body = xhr
+        .response_text()
+        .map_err(HttpError::network)?
+        .unwrap_or_default();
+    if !(200..300).contains(&status) {
+        return Err(HttpError::Status { status, body });
+    }
+    let headers = Headers::new().map_err(HttpError::network)?;
+    let raw = xhr.get_all_response_headers().map_err(HttpError::network)?;
+    for line in raw.split("\r\n") {
+        if let Some(ix) = line.find(':') {
+            let _ = headers.append(line[..ix].trim(), line[ix + 1..].trim());
+        }
+    }
+    Ok(Response {
+        status,
+        body,
+        headers,
+    })
+}
+
+/// A successful response
+#[derive(Clone, Debug)]
+pub struct Response {
+    pub status: u16,
+    pub body: String,
+    headers: Headers,
+}
+
+impl Response {
+    /// Get the value of a response header. Names are case-insensitive
+    pub fn header(&self, name: &str) -> Option<String> {
+        self.headers.get(name).ok().and_then(|value| value)
+    }
+
+    /// Decode the body as JSON
+    pub fn json<T: DeserializeOwned>(&self) -> Result<T, HttpError> {
+        serde_json::from_str(&self.body).map_err(|e| HttpError::Decode(e.to_string()))
+    }
+}
+
+impl<Msg: 'static> Cmd<Msg> {
+    /// Send a GET request, decoding the response as JSON.
+    /// The response type can be named with `Cmd::get_json::<T>(url, to_msg)`
+    pub fn get_json<T: DeserializeOwned + 'static>(
+        url: &str,
+        to_msg: impl FnOnce(Result<T, HttpError>) -> Msg + 'static,
+    ) -> Cmd<Msg> {
+        Cmd::fetch_json(Request::get(url), to_msg)
+    }
+
+    /// Send a POST request with a JSON body, decoding the response as JSON
+    pub fn post_json<T: DeserializeOwned + 'static>(
+        url: &str,
+        body: &impl Serialize,
+        to_msg: impl FnOnce(Result<T, HttpError>) -> Msg + 'static,
+    ) -> Cmd<Msg> {
+        Cmd::fetch_json(Request::post(url).json(body), to_msg)
+    }
+
+    /// Send a request, decoding the response as JSON
+    pub fn fetch_json<T: DeserializeOwned + 'static>(
+        request: Request,
+        to_msg: impl FnOnce(Result<T, HttpError>) -> Msg + 'static,
+    ) -> Cmd<Msg> {
+        Cmd::spawn(async move { Cmd::msg(to_msg(request.send_json().await)) })
+    }
+
+    /// Send a request, passing the response to `update`
+    pub fn fetch(
+        request: Request,
+        to_msg: impl FnOnce(Result<Response, HttpError>) -> Msg + 'static,
+    ) -> Cmd<Msg> {
+        Cmd::spawn(async move { Cmd::msg(to_msg(request.send().await)) })
+    }
+
+    /// Send a request, passing each `Transfer::Upload` and `Transfer::Download` progress
+    /// update to `update`, followed by the final `Transfer::Done` result.
+    ///
+    /// To be able to cancel the request, pass `Request::send_with_progress` to
+    /// `Cmd::stream_with_id` instead
+    pub fn fetch_with_progress(
+        request: Request,
+        to_msg: impl Fn(Transfer) -> Msg + 'static,
+    ) -> Cmd<Msg> {
+        let transfers = request.send_with_progress();
+        Cmd::stream(transfers.map(move |transfer| Cmd::msg(to_msg(transfer))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_cmds_name_response_type() {
+        let _: Cmd<String> = Cmd::get_json::<u32>("/count", |res| format!("{:?}", res));
+        let _: Cmd<String> = Cmd::post_json::<u32>("/count", &[1, 2], |res| format!("{:?}", res));
+        let _: Cmd<String> =
+            Cmd::fetch_json::<u32>(Request::get("/count"), |res| format!("{:?}", res));
+    }
+
+    #[test]
+    fn test_progress_fraction() {
+        let progress = |loaded, total| Progress { loaded, total };
+        assert_eq!(progress(50, Some(200)).fraction(), Some(0.25));
+        assert_eq!(progress(0, Some(0)).fraction(), Some(1.));
+        assert_eq!(progress(50, None).fraction(), None);
+    }
+
+    #[test]
+    fn test_send_with_progress_is_lazy() {
+        // Nothing is sent (so no browser is needed) until the stream is polled
+        drop(Request::get("/items").send_with_progress());
+
+        let mut bad_key = std::collections::HashMap::new();
+        bad_key.insert((1, 2), "not a string key");
+        let transfers = Request::post("/items").json(&bad_key).send_with_progress();
+        let transfers = futures::executor::block_on(transfers.collect::<Vec<_>>());
+        assert_eq!(transfers.len(), 1);
+        assert!(matches!(
+            transfers[0],
+            Transfer::Done(Err(HttpError::Encode(_)))
+        ));
+    }
+}
@@ -0,0 +1,290 @@
+//! Typed keyboard keys and shortcuts.
+//!
+//! `KeyName` is a typed version of `KeyboardEvent.key`, and `Shortcut` is a key
+//! plus modifiers parsed from strings like `"Ctrl+Enter"` or `"Shift+Alt+ArrowUp"`.
+//! See `event::on_keyboard` and `event::on_keys` for handlers which use them.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::event::Modifiers;
+
+/// A key, as reported by the `key` property of a keyboard event
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum KeyName {
+    Enter,
+    Escape,
+    Tab,
+    Backspace,
+    Delete,
+    Insert,
+    Space,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    ArrowUp,
+    ArrowDown,
+    ArrowLeft,
+    ArrowRight,
+    Shift,
+    Control,
+    Alt,
+    Meta,
+    /// Function keys F1 - F24
+    F(u8),
+    /// A key which produces a single character, e.g. 'a', 'A', '7' or '?'
+    Char(char),
+    /// Any other key, e.g. "CapsLock" or "MediaPlayPause"
+    Other(String),
+}
+
+impl KeyName {
+    /// Convert a `KeyboardEvent.key` value
+    pub fn from_key(value: &str) -> KeyName {
+        use KeyName::*;
+        match value {
+            "Enter" => Enter,
+            "Escape" | "Esc" => Escape,
+            "Tab" => Tab,
+            "Backspace" => Backspace,
+            "Delete" | "Del" => Delete,
+            "Insert" => Insert,
+            " " | "Spacebar" => Space,
+            "Home" => Home,
+            "End" => End,
+            "PageUp" => PageUp,
+            "PageDown" => PageDown,
+            "ArrowUp" | "Up" => ArrowUp,
+            "ArrowDown" | "Down" => ArrowDown,
+            "ArrowLeft" | "Left" => ArrowLeft,
+            "ArrowRight" | "Right" => ArrowRight,
+            "Shift" => Shift,
+            "Control" => Control,
+            "Alt" => Alt,
+            "Meta" | "OS" => Meta,
+            other => {
+                if let Some(n) = function_key(other) {
+                    return F(n);
+                }
+                let mut chars = other.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => Char(c),
+                    _ => Other(other.to_string()),
+                }
+            }
+        }
+    }
+}
+
+/// Parse "F1" - "F24" (case-insensitive)
+fn function_key(name: &str) -> Option<u8> {
+    let number = name.strip_prefix(&['f', 'F'][..])?;
+    match number.parse() {
+        Ok(n) if (1..=24).contains(&n) => Some(n),
+        _ => None,
+    }
+}
+
+/// A key combined with modifiers, e.g. Ctrl+S
+///
+/// Parsed from strings of `+`-separated modifiers followed by a key.
+/// Modifiers are `Ctrl`, `Alt`, `Shift` and `Meta` (or `Cmd`), and keys are either
+/// a single character or a key name such as `Enter`, `Esc`, `Space`, `Up` or `F5`.
+/// Everything is case-insensitive.
+///
+/// The modifiers must match exactly, so `"Ctrl+S"` does not fire for Ctrl+Alt+S.
+/// The exception is Shift for non-letter characters, since it is often
+/// needed just to type them: `"?"` matches however it was typed.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Shortcut {
+    pub key: KeyName,
+    pub modifiers: Modifiers,
+}
+
+impl Shortcut {
+    /// Whether a key press with the given modifiers triggers this shortcut
+    pub fn matches(&self, key: &KeyName, modifiers: Modifiers) -> bool {
+        match (&self.key, key) {
+            (KeyName::Char(want), KeyName::Char(got)) => {
+                if !want.to_lowercase().eq(got.to_lowercase()) {
+                    return false;
+                }
+                if want.is_alphabetic() {
+                    self.modifiers == modifiers
+                } else {
+                    Modifiers {
+                        shift: self.modifiers.shift,
+                        ..modifiers
+                    } == self.modifiers
+                }
+            }
+            (want, got) => want == got && self.modifiers == modifiers,
+        }
+    }
+}
+
+/// Error returned when parsing an invalid shortcut
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseShortcutError {
+    input: String,
+    reason: String,
+}
+
+impl fmt::Display for ParseShortcutError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid shortcut {:?}: {}", self.input, self.reason)
+    }
+}
+
+impl std::error::Error for ParseShortcutError {}
+
+impl FromStr for Shortcut {
+    type Err = ParseShortcutError;
+
+    fn from_str(input: &str) -> Result<Shortcut, ParseShortcutError> {
+        let fail = |reason: String| ParseShortcutError {
+            input: input.to_string(),
+            reason,
+        };
+        // The key itself may be '+', e.g. "Ctrl++"
+        let (mods, key) = if input == "+" {
+            ("", "+")
+        } else if let Some(mods) = input.strip_suffix("++") {
+            (mods, "+")
+        } else {
+            match input.rfind('+') {
+                Some(ix) => (&input[..ix], &input[ix + 1..]),
+                None => ("", input),
+            }
+        };
+
+        let mut modifiers = Modifiers::default();
+        if !mods.is_empty() {
+            for name in mods.split('+') {
+                match name.trim().to_lowercase().as_str() {
+                    "ctrl" | "control" => modifiers.ctrl = true,
+                    "alt" | "option" => modifiers.alt = true,
+                    "shift" => modifiers.shift = true,
+                    "meta" | "cmd" | "command" | "super" => modifiers.meta = true,
+                    "" => return Err(fail("empty modifier".into())),
+                    other => return Err(fail(format!("unknown modifier '{}'", other))),
+                }
+            }
+        }
+
+        let key = parse_key_name(key).ok_or_else(|| fail(format!("unknown key '{}'", key)))?;
+        Ok(Shortcut { key, modifiers })
+    }
+}
+
+fn parse_key_name(name: &str) -> Option<KeyName> {
+    use KeyName::*;
+    let mut chars = name.chars();
+    match (chars.next(), chars.next()) {
+        // Key events report the space bar as " ", which `from_key` treats as Space
+        (Some(' '), None) => return Some(Space),
+        (Some(c), None) => return Some(Char(c)),
+        _ => {}
+    }
+    if let Some(n) = function_key(name) {
+        return Some(F(n));
+    }
+    let key = match name.trim().to_lowercase().as_str() {
+        "enter" | "return" => Enter,
+        "esc" | "escape" => Escape,
+        "tab" => Tab,
+        "backspace" => Backspace,
+        "del" | "delete" => Delete,
+        "ins" | "insert" => Insert,
+        "space" => Space,
+        "home" => Home,
+        "end" => End,
+        "pageup" | "pgup" => PageUp,
+        "pagedown" | "pgdn" => PageDown,
+        "up" | "arrowup" => ArrowUp,
+        "down" | "arrowdown" => ArrowDown,
+        "left" | "arrowleft" => ArrowLeft,
+        "right" | "arrowright" => ArrowRight,
+        _ => return None,
+    };
+    Some(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mods(ctrl: bool, alt: bool, shift: bool, meta: bool) -> Modifiers {
+        Modifiers {
+            ctrl,
+            alt,
+            shift,
+            meta,
+        }
+    }
+
+    #[test]
+    fn test_parse_shortcut() {
+        let parse = |s: &str| s.parse::<Shortcut>().unwrap();
+        assert_eq!(
+            parse("Ctrl+Enter"),
+            Shortcut {
+                key: KeyName::Enter,
+                modifiers: mods(true, false, false, false)
+            }
+        );
+        assert_eq!(
+            parse("shift+alt+up"),
+            Shortcut {
+                key: KeyName::ArrowUp,
+                modifiers: mods(false, true, true, false)
+            }
+        );
+        assert_eq!(parse("Cmd+F5").key, KeyName::F(5));
+        assert_eq!(parse("Ctrl++").key, KeyName::Char('+'));
+        assert_eq!(parse("+").key, KeyName::Char('+'));
+        assert_eq!(parse("Esc").key, KeyName::Escape);
+
+        assert!("Ctrl+".parse::<Shortcut>().is_err());
+        assert!("Hyper+S".parse::<Shortcut>().is_err());
+        assert!("Ctrl+Nope".parse::<Shortcut>().is_err());
+        assert!("F25".parse::<Shortcut>().is_err());
+    }
+
+    #[test]
+    fn test_shortcut_matches() {
+        let ctrl_s: Shortcut = "Ctrl+S".parse().unwrap();
+        let ctrl = mods(true, false, false, false);
+        assert!(ctrl_s.matches(&KeyName::from_key("s"), ctrl));
+        assert!(ctrl_s.matches(&KeyName::from_key("S"), ctrl));
+        assert!(!ctrl_s.matches(&KeyName::from_key("s"), Modifiers::default()));
+        assert!(!ctrl_s.matches(&KeyName::from_key("s"), mods(true, false, true, false)));
+
+        let question: Shortcut = "?".parse().unwrap();
+        assert!(question.matches(&KeyName::from_key("?"), mods(false, false, true, false)));
+
+        let enter: Shortcut = "Enter".parse().unwrap();
+        assert!(enter.matches(&KeyName::from_key("Enter"), Modifiers::default()));
+        assert!(!enter.matches(&KeyName::from_key(" "), Modifiers::default()));
+
+        let space: Shortcut = " ".parse().unwrap();
+        assert_eq!(space, "Space".parse().unwrap());
+        assert!(space.matches(&KeyName::from_key(" "), Modifiers::default()));
+    }
+
+    #[test]
+    fn test_non_ascii_keys() {
+        assert_eq!(KeyName::from_key("é"), KeyName::Char('é'));
+        assert_eq!(KeyName::from_key("❤️"), KeyName::Other("❤️".into()));
+        assert_eq!(KeyName::from_key("ßF1"), KeyName::Other("ßF1".into()));
+
+        assert_eq!(
+            "Ctrl+é".parse::<Shortcut>().unwrap().key,
+            KeyName::Char('é')
+        );
+        assert!("Ctrl+éé".parse::<Shortcut>().is_err());
+        assert!("❤️".parse::<Shortcut>().is_err());
+        assert!("f١".parse::<Shortcut>().is_err());
+    }
+}
@@ -12,26 +12,38 @@ pub use reqwest;
 
 use derive_more::{Constructor, From};
 use downcast_rs::{impl_downcast, Downcast};
-use futures::FutureExt;
-use js_sys::Function;
+use futures::future::{self, AbortHandle};
+use futures::{stream, FutureExt, Stream, StreamExt};
 use wasm_bindgen::{closure::Closure, JsCast};
 use web_sys::{
-    Document, Element as DomElement, Event as DomEvent, HtmlDivElement, Location, Node,
-    PopStateEvent, Text, Window,
+    Document, Element as DomElement, Event as DomEvent, HtmlDivElement, Location, Node, Text,
+    Window,
 };
 
-use std::borrow::{BorrowMut, Cow};
-use std::collections::{BTreeSet, HashMap};
+use std::any::Any;
+use std::borrow::Cow;
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeSet, HashMap, VecDeque};
 use std::fmt::{self, Debug};
 use std::future::Future;
 use std::marker::PhantomData;
 use std::pin::Pin;
+use std::rc::Rc;
 
-use event::{Event, EventId, Listener};
-use html::{Attribute, Tag};
+use event::{Event, Listener};
+use html::{Attribute, NodeRef, Tag};
 
+pub mod browser;
+#[cfg(debug_assertions)]
+pub mod check;
+pub mod clipboard;
+pub mod drag;
 pub mod event;
+pub mod file;
+pub mod form;
 pub mod html;
+pub mod http;
+pub mod keyboard;
 pub mod program;
 pub mod socket;
 pub mod timer;
@@ -39,7 +51,8 @@ pub mod util;
 
 // TODO probably shouldn't re-export here
 pub use event::{on_click, on_dbl_click, on_input, on_keydown};
-pub use program::run;
+pub use program::{run, AppHandle};
+pub use timer::sleep;
 pub use url::Url;
 
 pub use wasm_bindgen::JsValue;
@@ -54,7 +67,7 @@ pub trait Model: 'static + Sized {
     ///
     /// A Model is never handled directly - all model updates happen through
     /// passing a Msg to the `Model::update` function.
-    type Msg;
+    type Msg: 'static;
 
     /// Update the application in response to a message.
     ///
@@ -84,7 +97,7 @@ pub trait Model: 'static + Sized {
     ///     div![
     ///         id("my-div"),
     ///         h1!("Hello from fig!"),
-    ///         button!("Click me!", on_click((), |()| Msg::ButtonClicked)),
+    ///         button!("Click me!", on_click(|| Msg::ButtonClicked)),
     ///         p!(format!("Click count: {}", self.click_count))
     ///     ]
     /// }
@@ -113,7 +126,9 @@ pub trait Model: 'static + Sized {
     fn on_url_request(req: UrlRequest) -> Cmd<Self::Msg> {
         util::on_url_request_intercept(req)
     }
-    /// A function called after the url changes.
+    /// A function called after the url changes, whether through `Cmd::push_url`,
+    /// `Cmd::replace_url`, or by moving through the history (e.g. with `Cmd::back`
+    /// or the browser's back button).
     ///
     /// Typically this function will parse the new url and notify the Model, so it can render the
     /// correct page. (By default does nothing)
@@ -155,14 +170,23 @@ type Str = Cow<'static, str>;
 
 /// The core application.
 ///
-/// At page load, the user create calls `application(..)` or similar, which creates an App
-/// in a thread-local. Unfortunately we have to use `unsafe` and stash it as a void pointer,
-/// because the App is generic over the Model and this cannot be expressed in safe Rust.
+/// At page load, the user create calls `run(..)` or similar, which creates an App
+/// and registers it in a thread-local under a fresh `AppId`. Because the App is generic
+/// over the Model, and we can't have generics in globals, the registry holds each App
+/// type-erased as an `Rc<dyn Any>`. Several apps (with different Models) may
+/// be registered at once, each mounted on its own target.
 ///
 /// The app kicks off an initial page render. Thereafter, any page event will call an internal function
-/// which casts the App back from the void pointer and call `app.update(msg)`, creating a new
-/// Model and forcing another page render. This is why all functions need to be tagged with the
-/// Model trait, so we know which Model to cast too!
+/// which looks up the App by id, downcasts it back to `RefCell<App<M>>` and call `app.update(msg)`,
+/// creating a new Model and forcing another page render. This is why all functions need to be
+/// tagged with the Model trait, so we know which Model to downcast to!
+///
+/// The App is only ever borrowed at the top of the stack, when a callback fires. Everything
+/// beneath (rendering, attaching listeners) is passed what it needs explicitly. Callbacks
+/// don't touch the App directly - they push their command onto a queue belonging to the App,
+/// which is drained in order. If a callback fires while the queue is already being drained
+/// (e.g. a `focus` event raised during rendering) the command simply waits its turn, so
+/// `Model::update` is never called re-entrantly.
 ///
 /// Any side-effect (e.g. fetching url, updating a model value) is handled ONLY through
 /// passing a `Cmd` to the `update` function. This ensures the functional reactive loop
@@ -170,21 +194,131 @@ type Str = Cow<'static, str>;
 ///
 /// For simplicity and safety, we keep App hidden from the user at all times.
 struct App<M: Model> {
+    id: AppId,
     window: Window,
     target: HtmlDivElement,
     model: M,
     current_vdom: Html<M>,
-    listeners: HashMap<EventId, (usize, Vec<Listener<M>>)>,
     subscriptions: Vec<Box<dyn Subscription<M>>>,
+    /// Listeners attached outside the vdom, e.g. to the window
+    global_listeners: Vec<Listener<M>>,
+    tasks: Tasks,
+    /// The contents of the target element before the app was mounted
+    original_html: String,
+}
+
+/// Whether the page needs rendering after running a command
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Rendering {
+    Needed,
+    Skip,
+    /// The page is being navigated away from, so nothing more should be done
+    Unloading,
+}
+
+/// A spawned future which has not yet resolved
+struct Task {
+    /// The id given with `Cmd::spawn_with_id`, if any
+    id: Option<Str>,
+    handle: AbortHandle,
+}
+
+/// The spawned futures of an App which have not yet resolved
+#[derive(Default)]
+struct Tasks {
+    /// Shared with the futures themselves, so they can remove themselves upon completion
+    running: Rc<RefCell<HashMap<u64, Task>>>,
+    next_id: u64,
+}
+
+impl Tasks {
+    /// Register a stream of commands, returning a future which passes each command to
+    /// `dispatch` until the stream is finished or cancelled. With `cancel_previous`,
+    /// running tasks with the same id are cancelled first
+    fn track<Msg: 'static>(
+        &mut self,
+        stream: Pin<Box<dyn Stream<Item = Cmd<Msg>>>>,
+        id: Option<Str>,
+        cancel_previous: bool,
+        dispatch: impl Fn(Cmd<Msg>) + 'static,
+    ) -> impl Future<Output = ()> {
+        if let (Some(id), true) = (&id, cancel_previous) {
+            self.cancel(id);
+        }
+        let task_id = self.next_id;
+        self.next_id += 1;
+        let running = self.running.clone();
+        let fut = stream.for_each(move |cmd| {
+            // An earlier command from the stream may have cancelled it
+            if running.borrow().contains_key(&task_id) {
+                dispatch(cmd);
+            }
+            future::ready(())
+        });
+        let (fut, handle) = future::abortable(fut);
+        self.running
+            .borrow_mut()
+            .insert(task_id, Task { id, handle });
+        let running = self.running.clone();
+        fut.map(move |res| {
+            // If the task was aborted, it has already been removed
+            if res.is_ok() {
+                running.borrow_mut().remove(&task_id);
+            }
+        })
+    }
+
+    /// Abort the tasks with the given id. The futures are dropped
+    /// the next time they are polled
+    fn cancel(&self, id: &str) {
+        self.running.borrow_mut().retain(|_, task| {
+            if task.id.as_deref() == Some(id) {
+                trace!("Cancelling task '{}'", id);
+                task.handle.abort();
+                false
+            } else {
+                true
+            }
+        });
+    }
+
+    fn abort_all(&self) {
+        for (_, task) in self.running.borrow_mut().drain() {
+            task.handle.abort();
+        }
+    }
+}
+
+/// Identifies a running App
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct AppId(u32);
+
+/// Identifies a destination registered by a mapped subscription
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+struct RouteId(u32);
+
+/// An App along with its queue of pending commands
+struct Runtime<M: Model> {
+    app: RefCell<App<M>>,
+    queue: RefCell<VecDeque<Cmd<M::Msg>>>,
 }
 
 thread_local! {
-    static APP: *mut u8 = std::ptr::null_mut();
+    static APPS: RefCell<HashMap<AppId, Rc<dyn Any>>> = RefCell::new(HashMap::new());
+    static NEXT_APP_ID: Cell<u32> = const { Cell::new(0) };
+    /// Destinations for the commands of mapped subscriptions (see `Sub::map`).
+    /// Each holds a `Box<dyn Fn(Cmd<Msg>)>` for the message type of the subscription
+    static ROUTES: RefCell<HashMap<RouteId, Rc<dyn Any>>> = RefCell::new(HashMap::new());
+    static NEXT_ROUTE_ID: Cell<u32> = const { Cell::new(0) };
 }
 
+// Listeners and subscriptions clean up after themselves when dropped,
+// so we need only cancel the futures and put back the original content
 impl<M: Model> Drop for App<M> {
     fn drop(&mut self) {
-        error!("Dropping app! This is an error")
+        info!("Unmounting app");
+        self.tasks.abort_all();
+        self.target.set_inner_html(&self.original_html);
     }
 }
 
@@ -194,71 +328,166 @@ impl<M: Model + Debug> Debug for App<M> {
     }
 }
 
-impl<M: Model> App<M> {
-    /// Update the model with the given Cmd
-    ///
-    /// Each command may trigger another command, and we do not want to render
-    /// each time, so we call update in an infinite loop and explicitly break
-    /// when we eventually receive a `None` command.
-    ///
-    /// After breaking the loop, we re-render the DOM
-    fn loop_update(&mut self, Cmd(mut cmd): Cmd<M::Msg>) -> JsResult<()> {
-        let mut loopct = 0;
-        loop {
-            loopct += 1;
-            match cmd {
-                CmdInner::None => break,
-                // return without rendering. Generally need a Very Good Reason for this
-                CmdInner::NoOp => return Ok(()),
-                CmdInner::Msg(msg) => {
-                    let Cmd(new_cmd) = self.model.update(msg);
-                    cmd = new_cmd; // we go again
-                }
-                CmdInner::Multiple(cmds) => {
-                    for cmd in cmds {
-                        // TODO hmm, this will actually re-render after each command
-                        // not sure if we the effort to avoid it
-                        self.loop_update(cmd)?
+/// The effects of running commands, kept apart from the command loop itself
+/// (see `update_and_render`) so that the loop can be tested without a browser
+trait Runner<Msg> {
+    /// Pass a message to `Model::update`
+    fn update(&mut self, msg: Msg) -> Cmd<Msg>;
+    /// Carry out any command other than a message or batch
+    fn effect(&mut self, cmd: CmdInner<Msg>) -> JsResult<Step<Msg>>;
+    /// Update the subscriptions and the DOM
+    fn render(&mut self) -> JsResult<()>;
+}
+
+/// What to do after carrying out an effect
+enum Step<Msg> {
+    /// Go round again with the given command
+    Continue(Cmd<Msg>),
+    Stop(Rendering),
+}
+
+/// Run a command, then render once if needed
+fn update_and_render<Msg>(runner: &mut impl Runner<Msg>, cmd: Cmd<Msg>) -> JsResult<()> {
+    if run_cmd(runner, cmd)? == Rendering::Needed {
+        runner.render()?;
+    }
+    Ok(())
+}
+
+/// Run a command, without rendering.
+///
+/// Each command may trigger another command, and we do not want to render
+/// each time, so we call update in an infinite loop and explicitly break
+/// when we eventually receive a `None` command.
+fn run_cmd<Msg>(runner: &mut impl Runner<Msg>, Cmd(mut cmd): Cmd<Msg>) -> JsResult<Rendering> {
+    let mut loopct = 0;
+    loop {
+        loopct += 1;
+        match cmd {
+            CmdInner::None => break,
+            // return without rendering. Generally need a Very Good Reason for this
+            CmdInner::NoOp => return Ok(Rendering::Skip),
+            CmdInner::Msg(msg) => {
+                let Cmd(new_cmd) = runner.update(msg);
+                cmd = new_cmd; // we go again
+            }
+            CmdInner::Multiple(cmds) => {
+                // Render once for the whole batch, unless every command opted out
+                let mut rendering = if cmds.is_empty() {
+                    Rendering::Needed
+                } else {
+                    Rendering::Skip
+                };
+                for cmd in cmds {
+                    match run_cmd(runner, cmd)? {
+                        Rendering::Unloading => return Ok(Rendering::Unloading),
+                        Rendering::Needed => rendering = Rendering::Needed,
+                        Rendering::Skip => {}
                     }
-                    break;
-                }
-                CmdInner::Spawn(request) => {
-                    let fut = request.map(|cmd| {
-                        App::<M>::with(|app| app.loop_update(cmd).expect("update failed"));
-                        ()
-                    });
-                    wasm_bindgen_futures::spawn_local(fut);
-                    break;
-                }
-                CmdInner::LoadUrl(urlstr) => {
-                    // Navigating away from the page
-                    let loc = self.window.location();
-                    loc.set_href(&urlstr).expect("Failed to set location");
-                    // This should ALWAYS force a reload so return without rendering
-                    return Ok(());
                 }
-                CmdInner::PushUrl(urlstr) => {
-                    // push the state...
-                    self.push_state(&urlstr).expect("Failed to push state");
-                    // Then grab the new href from Location
-                    let url = self.location().expect("No location");
-                    // and go round again
-                    cmd = M::on_url_change(url).0;
+                return Ok(rendering);
+            }
+            other => match runner.effect(other)? {
+                Step::Continue(Cmd(new_cmd)) => cmd = new_cmd,
+                Step::Stop(rendering) => return Ok(rendering),
+            },
+        }
+        if loopct > 100 {
+            panic!("Infinite loop!")
+        }
+    }
+    Ok(Rendering::Needed)
+}
+
+impl<M: Model> Runner<M::Msg> for App<M> {
+    fn update(&mut self, msg: M::Msg) -> Cmd<M::Msg> {
+        self.model.update(msg)
+    }
+
+    fn effect(&mut self, cmd: CmdInner<M::Msg>) -> JsResult<Step<M::Msg>> {
+        let step = match cmd {
+            CmdInner::Spawn {
+                stream,
+                id,
+                cancel_previous,
+            } => {
+                self.spawn(stream, id, cancel_previous);
+                Step::Stop(Rendering::Needed)
+            }
+            CmdInner::Cancel(id) => {
+                self.tasks.cancel(&id);
+                Step::Stop(Rendering::Needed)
+            }
+            CmdInner::LoadUrl(urlstr) => {
+                // Navigating away from the page
+                let loc = self.window.location();
+                loc.set_href(&urlstr).expect("Failed to set location");
+                // This should ALWAYS force a reload so return without rendering
+                Step::Stop(Rendering::Unloading)
+            }
+            CmdInner::PushUrl(urlstr) => {
+                // push the state...
+                self.push_state(&urlstr).expect("Failed to push state");
+                // Then grab the new href from Location
+                let url = self.location().expect("No location");
+                // and go round again
+                Step::Continue(M::on_url_change(url))
+            }
+            CmdInner::ReplaceUrl(urlstr) => {
+                self.replace_state(&urlstr)
+                    .expect("Failed to replace state");
+                let url = self.location().expect("No location");
+                Step::Continue(M::on_url_change(url))
+            }
+            CmdInner::Go(delta) => {
+                // Navigation happens asynchronously, and is picked up by the popstate handler
+                let history = self.window.history().expect("No history");
+                history.go_with_delta(delta)?;
+                Step::Stop(Rendering::Needed)
+            }
+            CmdInner::DispatchEvent(target, event) => {
+                match target.get() {
+                    Some(el) => {
+                        el.dispatch_event(&event)?;
+                    }
+                    None => error!("Cannot dispatch '{}': element not rendered", event.type_()),
                 }
+                Step::Stop(Rendering::Needed)
             }
-            if loopct > 100 {
-                panic!("Infinite loop!")
+            CmdInner::None | CmdInner::NoOp | CmdInner::Msg(_) | CmdInner::Multiple(_) => {
+                unreachable!("handled by run_cmd")
             }
-        }
+        };
+        Ok(step)
+    }
+
+    fn render(&mut self) -> JsResult<()> {
         trace!("Update subscriptions");
         self.update_subscriptions();
         // Don't render the new dom until we finish looping
         trace!("Update vdom");
         self.current_vdom = self.render_dom()?;
-        trace!("Registered events: {}", self.listeners.len());
-        // returns that it did rerender
+        #[cfg(debug_assertions)]
+        self.check_dom()?;
         Ok(())
     }
+}
+
+impl<M: Model> App<M> {
+    /// Spawn a stream of commands, keeping hold of a handle so it can be cancelled,
+    /// or aborted if the app is unmounted
+    fn spawn(
+        &mut self,
+        stream: Pin<Box<dyn Stream<Item = Cmd<M::Msg>>>>,
+        id: Option<Str>,
+        cancel_previous: bool,
+    ) {
+        let key = self.key();
+        let fut = self
+            .tasks
+            .track(stream, id, cancel_previous, move |cmd| key.dispatch(cmd));
+        wasm_bindgen_futures::spawn_local(fut);
+    }
 
     fn update_subscriptions(&mut self) {
         // TODO I don't think this function is very elegant
@@ -278,7 +507,7 @@ impl<M: Model> App<M> {
             }
             // Fell through above loop, so this is a new subscription.
             trace!("New subscription");
-            nsub.subscribe(Key::new());
+            nsub.subscribe(self.key());
             new_subs.push(nsub);
         }
         // Remove defunct subs
@@ -293,7 +522,7 @@ impl<M: Model> App<M> {
         self.subscriptions.extend(new_subs);
     }
 
-    fn render_dom(&self) -> JsResult<Html<M>> {
+    fn render_dom(&mut self) -> JsResult<Html<M>> {
         let new_vdom = self.model.view();
         let diff = diff_vdom(&self.current_vdom, &new_vdom);
         if let Diff::Unchanged = diff {
@@ -301,80 +530,208 @@ impl<M: Model> App<M> {
         } else {
             trace!("vdom diff: {:?}", diff);
             let document = self.window.document().expect("No document");
-            render_diff(&self.target, &[(0, diff)], &document)?;
+            let key = self.key();
+            render_diff(key, &self.target, &[(0, diff)], &document)?;
         }
         Ok(new_vdom)
     }
 
-    /// Run a function with the App as an argument. This involves unsafely casting from a
-    /// void pointer stashed in a thread-local!
-    fn with<R>(f: impl FnOnce(&mut Self) -> R) -> R {
-        APP.with(|mut ptr| {
-            let ptr: *mut u8 = **ptr.borrow_mut();
-            let ptr: *mut App<M> = ptr as *mut App<M>;
-            unsafe { f(&mut *ptr) }
-        })
+    /// Compare the live DOM against the current vdom, according to the `check::mode()`
+    #[cfg(debug_assertions)]
+    fn check_dom(&mut self) -> JsResult<()> {
+        let mode = check::mode();
+        let target: &web_sys::Node = &self.target;
+        if check::run(mode, target, &self.current_vdom) {
+            info!("Re-rendering from scratch");
+            self.rerender()?;
+        }
+        Ok(())
     }
 
-    /// Update the browser url
-    fn push_state(&self, url: &str) -> JsResult<()> {
-        let history = self.window.history().expect("No history");
-        history.push_state_with_url(&JsValue::NULL, "", Some(&url))
+    /// Throw away the DOM and render the current vdom from scratch
+    #[cfg(debug_assertions)]
+    fn rerender(&mut self) -> JsResult<()> {
+        // Creating the nodes re-attaches the events, detaching the old listeners
+        let document = self.window.document().expect("No document");
+        let key = self.key();
+        let root = self.current_vdom.create_dom_node(key, &document)?;
+        self.target.set_inner_html("");
+        self.target.append_child(&root)?;
+        Ok(())
     }
 
-    /// Fetch the browser url
-    fn location(&self) -> JsResult<url::Url> {
-        let urlstr = self.window.location().href()?;
-        url::Url::parse(&urlstr).map_err(|e| JsValue::from_str(&e.to_string()))
+    fn key(&self) -> Key<M> {
+        Key::new(self.id)
     }
 
-    fn stash_event_listener(&mut self, id: EventId, listener: Listener<M>) {
-        // Here we wish to stash the Listeners until the corresponding events are removed from the dom.
-        // However, the EventId is not guaranteed to be unique because it is possible for the "same"
-        // closure to exist on the page in several places at once
-        // (e.g. a logout button) SO we maintain a refcount the ensure we do not
-        // free it before we should. This is a bit of a hack, because we are forced to
-        // keep all listeners of the same EventId alive until none of them are needed any more
-        // XXX This is a potential memory leak.
-        let mut entry = self.listeners.entry(id).or_insert((0, Vec::new()));
-        entry.0 += 1; // increment refct
-        entry.1.push(listener);
+    /// Put the app on the heap and register it under a fresh id
+    fn register(app: impl FnOnce(AppId) -> App<M>) -> AppId {
+        let id = NEXT_APP_ID.with(|next| {
+            let id = next.get();
+            next.set(id + 1);
+            AppId(id)
+        });
+        let runtime: Rc<dyn Any> = Rc::new(Runtime {
+            app: RefCell::new(app(id)),
+            queue: RefCell::new(VecDeque::new()),
+        });
+        APPS.with(|apps| apps.borrow_mut().insert(id, runtime));
+        id
     }
 
-    fn remove_event_listener(&mut self, id: &EventId) {
-        if let Some(entry) = self.listeners.get_mut(id) {
-            entry.0 -= 1;
-            if entry.0 == 0 {
-                self.listeners.remove(id);
+    /// Remove the app from the registry, tearing it down.
+    ///
+    /// If the app is currently in use (i.e. we are being called from within an update)
+    /// the teardown happens as soon as it is released.
+    fn unregister(id: AppId) -> JsResult<()> {
+        APPS.with(|apps| apps.borrow_mut().remove(&id))
+            .map(drop)
+            .ok_or_else(|| JsValue::from_str("App not found"))
+    }
+
+    fn runtime(id: AppId) -> JsResult<Rc<Runtime<M>>> {
+        APPS.with(|apps| apps.borrow().get(&id).cloned())
+            .ok_or_else(|| JsValue::from_str("App not found"))?
+            .downcast::<Runtime<M>>()
+            .map_err(|_| JsValue::from_str("App has unexpected Model type"))
+    }
+
+    /// Run a function with the App as an argument.
+    ///
+    /// Fails if the app has been unmounted, or if it is already in use further up the stack.
+    fn with<R>(id: AppId, f: impl FnOnce(&mut Self) -> R) -> JsResult<R> {
+        let runtime = App::<M>::runtime(id)?;
+        let mut app = runtime
+            .app
+            .try_borrow_mut()
+            .map_err(|_| JsValue::from_str("Reentrant call: the app is already in use"))?;
+        let res = f(&mut app);
+        Ok(res)
+    }
+
+    /// Queue a command, then process the queue in order.
+    ///
+    /// If the queue is already being processed further up the stack, we leave the command
+    /// for that to pick up instead. An error while processing one command is logged
+    /// and does not prevent the rest of the queue from running.
+    fn dispatch(id: AppId, cmd: Cmd<M::Msg>) -> JsResult<()> {
+        let runtime = App::<M>::runtime(id)?;
+        runtime.queue.borrow_mut().push_back(cmd);
+        let mut app = match runtime.app.try_borrow_mut() {
+            Ok(app) => app,
+            Err(_) => {
+                trace!("App busy, command queued");
+                return Ok(());
+            }
+        };
+        loop {
+            // Careful not to hold the queue borrow while updating, as that is
+            // exactly when new commands may arrive
+            let next = runtime.queue.borrow_mut().pop_front();
+            match next {
+                Some(cmd) => {
+                    if let Err(e) = update_and_render(&mut *app, cmd) {
+                        error!("Update error: {:?}", e);
+                    }
+                }
+                None => break Ok(()),
             }
         }
     }
 
+    /// Update the browser url
+    fn push_state(&self, url: &str) -> JsResult<()> {
+        let history = self.window.history().expect("No history");
+        history.push_state_with_url(&JsValue::NULL, "", Some(url))
+    }
+
+    /// Update the browser url without adding an entry to the history
+    fn replace_state(&self, url: &str) -> JsResult<()> {
+        let history = self.window.history().expect("No history");
+        history.replace_state_with_url(&JsValue::NULL, "", Some(url))
+    }
+
+    /// Fetch the browser url
+    fn location(&self) -> JsResult<url::Url> {
+        current_url(&self.window)
+    }
+}
+
+impl<M: Model> App<M> {
     /// Set the handler that will trigger a Route change when `window.popstate`
     /// event is fired, e.g by the `back` button
-    fn set_popstate_handler(&self) {
-        let handler = move |_e: PopStateEvent| {
+    ///
+    /// We add a listener rather than setting `window.onpopstate` so that every
+    /// app on the page is notified.
+    fn set_popstate_handler(&mut self) -> JsResult<()> {
+        let window = self.window.clone();
+        let handler = move |_e: DomEvent| {
             // Event state is just some state that was associated with the current url
             // when pushState was called. Since we don't use it, it will be `null`
             // We just want to force a page update with the new Route
-            App::<M>::with(|app| {
-                let url = app.location().expect("No location");
-                M::on_url_change(url)
-            })
+            let url = current_url(&window).expect("No location");
+            M::on_url_change(url)
         };
-        let cb = crate::event::closure1::<M, _, _>(handler);
-        let onpop: &Function = cb.as_ref().unchecked_ref();
-        self.window.set_onpopstate(Some(onpop));
-        Box::leak(Box::new(cb));
+        let listener =
+            event::attach_event_handler(self.key(), self.window.clone(), "popstate", handler)?;
+        self.global_listeners.push(listener);
+        Ok(())
     }
 }
 
+/// Fetch the browser url
+fn current_url(window: &Window) -> JsResult<url::Url> {
+    let urlstr = window.location().href()?;
+    url::Url::parse(&urlstr).map_err(|e| JsValue::from_str(&e.to_string()))
+}
+
 /// A token which grants permission to use various library features
-pub struct Key<M: Model>(PhantomData<M>);
+///
+/// Each key belongs to a particular running App, and callbacks created through it
+/// will update that App.
+pub struct Key<M: Model> {
+    app_id: AppId,
+    /// Set for the keys of mapped subscriptions, whose commands must be
+    /// converted before they reach the App
+    route: Option<RouteId>,
+    marker: PhantomData<M>,
+}
+
+impl<M: Model> Clone for Key<M> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<M: Model> Copy for Key<M> {}
 
 impl<M: Model> Key<M> {
-    fn new() -> Key<M> {
-        Key(PhantomData)
+    fn new(app_id: AppId) -> Key<M> {
+        Key {
+            app_id,
+            route: None,
+            marker: PhantomData,
+        }
+    }
+
+    /// Send a command to the App
+    pub(crate) fn dispatch(&self, cmd: Cmd<M::Msg>) {
+        let route = match self.route {
+            None => {
+                App::<M>::dispatch(self.app_id, cmd)
+                    .unwrap_or_else(|e| error!("Update error: {:?}", e));
+                return;
+            }
+            // Don't hold the borrow, the route may be removed while it runs
+            Some(id) => ROUTES.with(|routes| routes.borrow().get(&id).cloned()),
+        };
+        match route
+            .as_ref()
+            .and_then(|route| route.downcast_ref::<Box<dyn Fn(Cmd<M::Msg>)>>())
+        {
+            Some(route) => route(cmd),
+            None => trace!("Subscription removed, command dropped"),
+        }
     }
 
     /// Take a zero-argument callback and hook it into the main event loop
@@ -382,7 +739,7 @@ impl<M: Model> Key<M> {
         &self,
         handler: F,
     ) -> Closure<dyn FnMut()> {
-        event::closure0::<M, F>(handler)
+        event::closure0::<M, F>(*self, handler)
     }
 
     /// Take a single-argument callback and hook it into the main event loop
@@ -393,7 +750,7 @@ impl<M: Model> Key<M> {
     where
         T: wasm_bindgen::convert::FromWasmAbi + 'static,
     {
-        event::closure1::<M, T, F>(handler)
+        event::closure1::<M, T, F>(*self, handler)
     }
 }
 
@@ -406,6 +763,7 @@ pub enum UrlRequest {
 // Intercept link clicks and handle them ourselves.
 // Requires us to set a global listener for clicks.
 fn set_link_click_handler<M: Model, F: Fn(UrlRequest) -> Cmd<M::Msg> + 'static>(
+    key: Key<M>,
     location: Location,
     root: DomElement,
     handler: F,
@@ -437,7 +795,7 @@ fn set_link_click_handler<M: Model, F: Fn(UrlRequest) -> Cmd<M::Msg> + 'static>(
         handler(req)
     };
     // set the handler on all clicks, on the given (root) node
-    event::attach_event_handler(root, "click", cb)
+    event::attach_event_handler(key, root, "click", cb)
 }
 
 #[derive(Clone, Debug)]
@@ -446,20 +804,41 @@ enum Delta<T> {
     Remove(T),
 }
 
+/// How the events of an updated element have changed.
+///
+/// There is no 'remove' - any events left behind in the old vdom are
+/// detached when it is dropped.
+#[derive(Clone)]
+enum EventDelta<'a, M: Model> {
+    /// A new event, to be attached
+    Add(&'a Event<M>),
+    /// An event of the same kind as before. The existing listener is handed over
+    Keep {
+        old: &'a Event<M>,
+        new: &'a Event<M>,
+    },
+}
+
+/// A change to the node refs pointing at an existing element
+#[derive(Clone)]
+struct NodeRefDelta<'a> {
+    /// A ref which no longer points at the element. Siblings are patched in order,
+    /// so it may already have been pointed at an earlier sibling, in which case it is kept
+    old: Option<&'a NodeRef>,
+    /// A ref to be pointed at the element
+    new: Option<&'a NodeRef>,
+}
+
 /// A fig describing which nodes have changed and how
 #[derive(Clone)]
 enum Diff<'a, M: Model> {
     Insert(&'a Html<M>),
-    Replace {
-        with: &'a Html<M>,
-        events_to_rm: Vec<EventId>,
-    },
-    Remove {
-        events_to_rm: Vec<EventId>,
-    },
+    Replace(&'a Html<M>),
+    Remove,
     Update {
         attrs: Vec<Delta<&'a Attribute>>,
-        events: Vec<Delta<&'a Event<M>>>,
+        events: Vec<EventDelta<'a, M>>,
+        node_ref: Option<NodeRefDelta<'a>>,
         children: Vec<(u32, Diff<'a, M>)>,
     },
     Unchanged,
@@ -470,12 +849,13 @@ impl<'a, M: Model> Debug for Diff<'a, M> {
         use Diff::*;
         let txt = match self {
             Insert(_) => "Insert",
-            Replace { .. } => "Replace",
-            Remove { .. } => "Remove",
+            Replace(_) => "Replace",
+            Remove => "Remove",
             Unchanged => "Unchanged",
             Update {
                 attrs,
                 events,
+                node_ref,
                 children,
             } => {
                 write!(f, "Update {{ ")?;
@@ -485,6 +865,9 @@ impl<'a, M: Model> Debug for Diff<'a, M> {
                 if !events.is_empty() {
                     write!(f, "events ")?;
                 }
+                if node_ref.is_some() {
+                    write!(f, "node_ref ")?;
+                }
                 if !children.is_empty() {
                     write!(f, "children: [")?;
                     for c in children {
@@ -515,33 +898,18 @@ fn diff_vdom<'a, M: Model>(old: &'a Html<M>, new: &'a Html<M>) -> Diff<'a, M> {
             return if t1 == t2 {
                 Diff::Unchanged
             } else {
-                Diff::Replace {
-                    with: new,
-                    events_to_rm: Vec::new(),
-                }
+                Diff::Replace(new)
             }
         }
-        (Html::Text(_), Html::Element(_)) => {
-            return Diff::Replace {
-                with: new,
-                events_to_rm: Vec::new(),
-            }
-        }
-        (Html::Element(_), Html::Text(_)) => {
-            return Diff::Replace {
-                with: new,
-                events_to_rm: old.get_nested_event_ids(),
-            }
+        (Html::Text(_), Html::Element(_)) | (Html::Element(_), Html::Text(_)) => {
+            return Diff::Replace(new)
         }
         (Html::Element(e1), Html::Element(e2)) => (e1, e2),
     };
 
     if old_el.tag != new_el.tag {
         // assume everything can be nuked
-        return Diff::Replace {
-            with: new,
-            events_to_rm: old.get_nested_event_ids(),
-        };
+        return Diff::Replace(new);
     }
 
     let attrs = if old_el.attrs == new_el.attrs {
@@ -558,19 +926,30 @@ fn diff_vdom<'a, M: Model>(old: &'a Html<M>, new: &'a Html<M>) -> Diff<'a, M> {
         }
         deltas
     };
-    let events = if old_el.events == new_el.events {
-        Vec::new()
-    } else {
-        let mut deltas = Vec::new();
-        let oldset: BTreeSet<&Event<M>> = old_el.events.iter().collect();
-        let newset: BTreeSet<&Event<M>> = new_el.events.iter().collect();
-        for &attr in oldset.difference(&newset) {
-            deltas.push(Delta::Remove(attr))
-        }
-        for &attr in newset.difference(&oldset) {
-            deltas.push(Delta::Add(attr))
-        }
-        deltas
+    // Pair up each new event with an old event of the same kind, if there is one
+    let mut old_events: Vec<&Event<M>> = old_el.events.iter().collect();
+    let events: Vec<EventDelta<M>> = new_el
+        .events
+        .iter()
+        .map(
+            |new| match old_events.iter().position(|old| old.same_kind(new)) {
+                Some(pos) => EventDelta::Keep {
+                    old: old_events.remove(pos),
+                    new,
+                },
+                None => EventDelta::Add(new),
+            },
+        )
+        .collect();
+
+    // If the element is still referenced by the same node ref, it stays valid
+    let node_ref = match (&old_el.node_ref, &new_el.node_ref) {
+        (Some(old), Some(new)) if old.ptr_eq(new) => None,
+        (None, None) => None,
+        (old, new) => Some(NodeRefDelta {
+            old: old.as_ref(),
+            new: new.as_ref(),
+        }),
     };
 
     let mut child_diffs = Vec::new();
@@ -597,24 +976,27 @@ fn diff_vdom<'a, M: Model>(old: &'a Html<M>, new: &'a Html<M>) -> Diff<'a, M> {
         }
     } else {
         for ix in nextct..curct {
-            let events_to_rm = old_el.children[ix].get_nested_event_ids();
-            child_diffs.push((ix as u32, Diff::Remove { events_to_rm }))
+            child_diffs.push((ix as u32, Diff::Remove))
         }
     }
 
     child_diffs.sort_by_key(|t| t.0);
-    if attrs.is_empty() && events.is_empty() && child_diffs.is_empty() {
+    // Even if no events were added, existing listeners must be handed over to the new vdom,
+    // so we can only skip the element if it has no events at all
+    if attrs.is_empty() && events.is_empty() && node_ref.is_none() && child_diffs.is_empty() {
         Diff::Unchanged
     } else {
         Diff::Update {
             attrs,
             events,
+            node_ref,
             children: child_diffs,
         }
     }
 }
 
 fn render_diff<'a, M: Model>(
+    key: Key<M>,
     this_el: &Node,
     child_diffs: &[(u32, Diff<'a, M>)],
     doc: &Document,
@@ -633,25 +1015,16 @@ fn render_diff<'a, M: Model>(
             Diff::Unchanged => (),
             Diff::Insert(node) => {
                 // Is there already a node at this index?
-                let new_el = node.create_dom_node(doc)?;
+                let new_el = node.create_dom_node(key, doc)?;
                 // XXX insert child, not append!
                 this_el.append_child(&new_el)?;
             }
-            Diff::Replace {
-                with: node,
-                events_to_rm,
-            } => {
-                for event_id in events_to_rm {
-                    App::<M>::with(|app| app.remove_event_listener(event_id));
-                }
+            Diff::Replace(node) => {
                 let old_el = child_els.get(ix).expect("bad replace node index");
-                let new_el = node.create_dom_node(doc)?;
+                let new_el = node.create_dom_node(key, doc)?;
                 this_el.replace_child(&new_el, &old_el)?;
             }
-            Diff::Remove { events_to_rm } => {
-                for event_id in events_to_rm {
-                    App::<M>::with(|app| app.remove_event_listener(event_id));
-                }
+            Diff::Remove => {
                 let old_el = child_els.get(ix).expect("bad remove node index");
                 this_el.remove_child(&old_el)?;
                 rmct += 1;
@@ -659,18 +1032,28 @@ fn render_diff<'a, M: Model>(
             Diff::Update {
                 attrs,
                 events,
+                node_ref,
                 children,
             } => {
                 let child_el: Node = child_els.get(ix).expect("bad node index");
+                if let Some(NodeRefDelta { old, new }) = node_ref {
+                    let el = child_el.dyn_ref::<DomElement>();
+                    if let (Some(old), Some(el)) = (old, el) {
+                        old.release(el);
+                    }
+                    if let Some(new) = new {
+                        new.set(el.cloned());
+                    }
+                }
                 if !events.is_empty() {
                     let el: &DomElement = child_el.dyn_ref().expect("Not an element");
-                    update_events(&el, &events)?;
+                    update_events(key, &el, &events)?;
                 }
                 if !attrs.is_empty() {
                     let el: &DomElement = child_el.dyn_ref().expect("Not an element");
                     update_attrs(&el, &attrs)?;
                 }
-                render_diff(&child_el, &*children, doc)?;
+                render_diff(key, &child_el, &*children, doc)?;
             }
         }
     }
@@ -689,9 +1072,19 @@ enum CmdInner<Msg> {
     NoOp,
     Msg(Msg),
     Multiple(Vec<Cmd<Msg>>),
-    Spawn(Pin<Box<dyn Future<Output = Cmd<Msg>>>>),
+    Spawn {
+        stream: Pin<Box<dyn Stream<Item = Cmd<Msg>>>>,
+        id: Option<Str>,
+        /// Cancel any running tasks with the same id first
+        cancel_previous: bool,
+    },
+    Cancel(Str),
     LoadUrl(Str),
     PushUrl(Str),
+    ReplaceUrl(Str),
+    /// Move through the session history by the given number of pages
+    Go(i32),
+    DispatchEvent(NodeRef, web_sys::CustomEvent),
 }
 
 impl<Msg> Cmd<Msg> {
@@ -709,18 +1102,83 @@ impl<Msg> Cmd<Msg> {
         Cmd(CmdInner::Msg(msg))
     }
 
-    /// Run multiple commands. The commands are run in turn and the page is
-    /// rendered after each has completed.
+    /// Run multiple commands. The commands are run in turn, then the subscriptions
+    /// are updated and the page is rendered once at the end.
     ///
     /// This command is useful for spawning multiple futures at once
-    pub fn multiple(msgs: impl IntoIterator<Item = Cmd<Msg>>) -> Self {
-        Cmd(CmdInner::Multiple(msgs.into_iter().collect()))
+    pub fn batch(cmds: impl IntoIterator<Item = Cmd<Msg>>) -> Self {
+        Cmd(CmdInner::Multiple(cmds.into_iter().collect()))
+    }
+
+    /// Run multiple commands. Equivalent to `Cmd::batch`
+    pub fn multiple(cmds: impl IntoIterator<Item = Cmd<Msg>>) -> Self {
+        Cmd::batch(cmds)
     }
 
     /// Spawn a future. When the future resolves, the message will be run in the
     /// event loop.
     pub fn spawn(fut: impl Future<Output = Cmd<Msg>> + 'static) -> Self {
-        Cmd(CmdInner::Spawn(Box::pin(fut)))
+        Cmd(CmdInner::Spawn {
+            stream: Box::pin(stream::once(fut)),
+            id: None,
+            cancel_previous: false,
+        })
+    }
+
+    /// Run each command produced by a stream as it arrives, e.g. to report the
+    /// progress of a long-running task before its result
+    pub fn stream(stream: impl Stream<Item = Cmd<Msg>> + 'static) -> Self {
+        Cmd(CmdInner::Spawn {
+            stream: Box::pin(stream),
+            id: None,
+            cancel_previous: false,
+        })
+    }
+
+    /// Spawn a future which can be cancelled with `Cmd::cancel`.
+    /// Several futures may share an id, in which case they are cancelled together
+    pub fn spawn_with_id(
+        id: impl Into<Str>,
+        fut: impl Future<Output = Cmd<Msg>> + 'static,
+    ) -> Self {
+        Cmd(CmdInner::Spawn {
+            stream: Box::pin(stream::once(fut)),
+            id: Some(id.into()),
+            cancel_previous: false,
+        })
+    }
+
+    /// Run each command produced by a stream as it arrives, like `Cmd::stream`.
+    /// The stream can be cancelled with `Cmd::cancel`
+    pub fn stream_with_id(
+        id: impl Into<Str>,
+        stream: impl Stream<Item = Cmd<Msg>> + 'static,
+    ) -> Self {
+        Cmd(CmdInner::Spawn {
+            stream: Box::pin(stream),
+            id: Some(id.into()),
+            cancel_previous: false,
+        })
+    }
+
+    /// Spawn a future, first cancelling any running futures with the same id.
+    ///
+    /// Useful when only the latest result matters, e.g. to stop the results of a
+    /// slow search request overwriting those of a newer one
+    pub fn spawn_latest(id: impl Into<Str>, fut: impl Future<Output = Cmd<Msg>> + 'static) -> Self {
+        Cmd(CmdInner::Spawn {
+            stream: Box::pin(stream::once(fut)),
+            id: Some(id.into()),
+            cancel_previous: true,
+        })
+    }
+
+    /// Cancel the running futures spawned with the given id, so their messages never arrive.
+    ///
+    /// The futures are dropped, which also clears any `fig::sleep` timers they are waiting
+    /// on. To abort a `fetch` request too, pass it the signal of a `util::AbortOnDrop`
+    pub fn cancel(id: impl Into<Str>) -> Self {
+        Cmd(CmdInner::Cancel(id.into()))
     }
 
     // TODO require a Key to change the url
@@ -729,6 +1187,53 @@ impl<Msg> Cmd<Msg> {
         Cmd(CmdInner::PushUrl(format!("{}", url).into()))
     }
 
+    /// Change the page url to the supplied path, replacing the current history entry.
+    /// Useful for redirects, so the back button skips the redirecting page
+    pub fn replace_url(url: impl std::fmt::Display) -> Self {
+        Cmd(CmdInner::ReplaceUrl(format!("{}", url).into()))
+    }
+
+    /// Go back to the previous page, like the browser's back button
+    pub fn back() -> Self {
+        Cmd::go(-1)
+    }
+
+    /// Go forward to the next page, like the browser's forward button
+    pub fn forward() -> Self {
+        Cmd::go(1)
+    }
+
+    /// Move through the history by `delta` pages, e.g. `-2` to go back two pages.
+    /// `Model::on_url_change` is called once the browser has navigated.
+    /// Does nothing if there is no such page
+    pub fn go(delta: i32) -> Self {
+        Cmd(CmdInner::Go(delta))
+    }
+
+    /// Fire a `CustomEvent` with the given name from the referenced element, e.g. to
+    /// communicate with a web component or other JS code. The event bubbles, and
+    /// `detail` is serialized to a JS value with serde.
+    pub fn dispatch_event(target: &NodeRef, name: &str, detail: &impl serde::Serialize) -> Self {
+        let mut init = web_sys::CustomEventInit::new();
+        init.bubbles(true);
+        let detail = serde_json::to_string(detail)
+            .map_err(|e| JsValue::from_str(&e.to_string()))
+            .and_then(|json| js_sys::JSON::parse(&json));
+        match detail {
+            Ok(detail) => {
+                init.detail(&detail);
+            }
+            Err(e) => error!("Failed to serialize detail of '{}': {:?}", name, e),
+        }
+        match web_sys::CustomEvent::new_with_event_init_dict(name, &init) {
+            Ok(event) => Cmd(CmdInner::DispatchEvent(target.clone(), event)),
+            Err(e) => {
+                error!("Failed to create event '{}': {:?}", name, e);
+                Cmd::none()
+            }
+        }
+    }
+
     // TODO require a Key to load the url
     /// Load the supplied url. This forces a page reload (destroying the current app).
     pub fn load_url(url: impl Into<Str>) -> Self {
@@ -736,6 +1241,42 @@ impl<Msg> Cmd<Msg> {
     }
 }
 
+impl<Msg: 'static> Cmd<Msg> {
+    /// Convert the message type of the command, including the messages of any
+    /// spawned futures. Useful to return the commands of a child component from
+    /// its parent's `update`
+    pub fn map<B: 'static>(self, f: impl Fn(Msg) -> B + 'static) -> Cmd<B> {
+        self.map_rc(Rc::new(f))
+    }
+
+    fn map_rc<B: 'static>(self, f: Rc<dyn Fn(Msg) -> B>) -> Cmd<B> {
+        let inner = match self.0 {
+            CmdInner::None => CmdInner::None,
+            CmdInner::NoOp => CmdInner::NoOp,
+            CmdInner::Msg(msg) => CmdInner::Msg(f(msg)),
+            CmdInner::Multiple(cmds) => {
+                CmdInner::Multiple(cmds.into_iter().map(|cmd| cmd.map_rc(f.clone())).collect())
+            }
+            CmdInner::Spawn {
+                stream,
+                id,
+                cancel_previous,
+            } => CmdInner::Spawn {
+                stream: Box::pin(stream.map(move |cmd| cmd.map_rc(f.clone()))),
+                id,
+                cancel_previous,
+            },
+            CmdInner::Cancel(id) => CmdInner::Cancel(id),
+            CmdInner::LoadUrl(url) => CmdInner::LoadUrl(url),
+            CmdInner::PushUrl(url) => CmdInner::PushUrl(url),
+            CmdInner::ReplaceUrl(url) => CmdInner::ReplaceUrl(url),
+            CmdInner::Go(delta) => CmdInner::Go(delta),
+            CmdInner::DispatchEvent(target, event) => CmdInner::DispatchEvent(target, event),
+        };
+        Cmd(inner)
+    }
+}
+
 impl<T> From<T> for Cmd<T> {
     fn from(t: T) -> Self {
         Cmd::msg(t)
@@ -756,6 +1297,67 @@ impl<M: Model> Sub<M> {
     pub fn new(ss: Vec<Box<dyn Subscription<M>>>) -> Self {
         Sub(ss)
     }
+
+    /// Combine several sets of subscriptions
+    pub fn batch(subs: impl IntoIterator<Item = Sub<M>>) -> Self {
+        Sub(subs.into_iter().flat_map(|sub| sub.0).collect())
+    }
+
+    /// Convert the messages produced by the subscriptions, e.g. to include the
+    /// subscriptions of a child component in its parent's
+    pub fn map<P: Model>(self, f: fn(M::Msg) -> P::Msg) -> Sub<P> {
+        let subs = self.0.into_iter().map(|inner| {
+            Box::new(MappedSub {
+                inner,
+                map: f,
+                route: None,
+            }) as Box<dyn Subscription<P>>
+        });
+        Sub(subs.collect())
+    }
+}
+
+/// A subscription whose messages are converted with `Sub::map`
+struct MappedSub<C: Model, P: Model> {
+    inner: Box<dyn Subscription<C>>,
+    map: fn(C::Msg) -> P::Msg,
+    route: Option<RouteId>,
+}
+
+impl<C: Model, P: Model> Subscription<P> for MappedSub<C, P> {
+    fn subscribe(&mut self, key: Key<P>) {
+        let id = NEXT_ROUTE_ID.with(|next| {
+            let id = next.get();
+            next.set(id + 1);
+            RouteId(id)
+        });
+        let map = self.map;
+        let route: Box<dyn Fn(Cmd<C::Msg>)> = Box::new(move |cmd| key.dispatch(cmd.map(map)));
+        let route: Rc<dyn Any> = Rc::new(route);
+        ROUTES.with(|routes| routes.borrow_mut().insert(id, route));
+        self.route = Some(id);
+        self.inner.subscribe(Key {
+            app_id: key.app_id,
+            route: Some(id),
+            marker: PhantomData,
+        });
+    }
+
+    fn sub_eq(&self, other: &dyn Subscription<P>) -> bool {
+        if let Some(o) = other.downcast_ref::<Self>() {
+            std::ptr::fn_addr_eq(self.map, o.map) && self.inner.sub_eq(&*o.inner)
+        } else {
+            false
+        }
+    }
+}
+
+impl<C: Model, P: Model> Drop for MappedSub<C, P> {
+    fn drop(&mut self) {
+        if let Some(id) = self.route {
+            ROUTES.with(|routes| routes.borrow_mut().remove(&id));
+        }
+    }
 }
 
 pub trait Subscription<M: Model>: Downcast {
@@ -783,32 +1385,10 @@ impl<M: Model> std::fmt::Display for Html<M> {
 }
 
 impl<M: Model> Html<M> {
-    fn create_dom_node(&self, doc: &Document) -> JsResult<Node> {
+    fn create_dom_node(&self, key: Key<M>, doc: &Document) -> JsResult<Node> {
         match self {
             Html::Text(text) => Text::new_with_data(text).map(|t| t.unchecked_into()),
-            Html::Element(elem) => elem.create_dom_node(doc).map(|t| t.unchecked_into()),
-        }
-    }
-
-    /// Find all events attached the current node and all child nodes. This is useful
-    /// because we have to clean up the events 'manually' when a node is removed
-    fn get_nested_event_ids(&self) -> Vec<EventId> {
-        let mut events_to_rm = Vec::new();
-        self.recursively_gather_event_ids(&mut events_to_rm);
-        events_to_rm
-    }
-
-    fn recursively_gather_event_ids(&self, ids: &mut Vec<EventId>) {
-        match &self {
-            Html::Text(_) => (),
-            Html::Element(elem) => {
-                for ev in &elem.events {
-                    ids.push(ev.id());
-                }
-                for c in &elem.children {
-                    c.recursively_gather_event_ids(ids)
-                }
-            }
+            Html::Element(elem) => elem.create_dom_node(key, doc).map(|t| t.unchecked_into()),
         }
     }
 }
@@ -819,13 +1399,14 @@ pub struct Element<M: Model> {
     tag: Tag,
     attrs: Vec<Attribute>,
     events: Vec<Event<M>>,
+    node_ref: Option<NodeRef>,
     children: Vec<Html<M>>,
 }
 
 impl<M: Model> Element<M> {
     /// Create an empty tagged element
     pub fn tag(tag: Tag) -> Element<M> {
-        Element::new(tag, Vec::new(), Vec::new(), Vec::new())
+        Element::new(tag, Vec::new(), Vec::new(), None, Vec::new())
     }
 }
 
@@ -847,15 +1428,17 @@ impl<M: Model> Element<M> {
         Ok(())
     }
 
-    fn create_dom_node(&self, document: &Document) -> JsResult<DomElement> {
+    fn create_dom_node(&self, key: Key<M>, document: &Document) -> JsResult<DomElement> {
         let element = document.create_element(&self.tag.to_string())?;
         self.add_attrs(&element)?;
         for event in &self.events {
-            let listener = event::attach_event_listener(event, &element)?;
-            App::<M>::with(|app| app.stash_event_listener(event.id(), listener));
+            event.attach(key, &element)?;
+        }
+        if let Some(node_ref) = &self.node_ref {
+            node_ref.set(Some(element.clone()));
         }
         for child in &self.children {
-            let child_elem = child.create_dom_node(document)?;
+            let child_elem = child.create_dom_node(key, document)?;
             element.append_child(&child_elem)?;
         }
         Ok(element)
@@ -873,18 +1456,84 @@ fn remove_attr_from_element(attr: &Attribute, element: &DomElement) -> JsResult<
     element.remove_attribute(key)
 }
 
-fn update_events<M: Model>(element: &DomElement, events: &[Delta<&Event<M>>]) -> JsResult<()> {
-    for delta in events {
-        match delta {
-            Delta::Add(event) => {
-                let listener = event::attach_event_listener(event, &element)?;
-                App::<M>::with(|app| app.stash_event_listener(event.id(), listener));
+/// The `checked`, `selected` and `value` attributes only set the initial state
+/// of a form control. Once the user has interacted with it, the live state is
+/// held in the corresponding property, which must be set too.
+///
+/// Returns the final state of each such property changed by `attrs`. A changed
+/// value is both removed and added, but is written once, so that clearing it
+/// first does not move the caret of a text input.
+fn form_properties<'a>(attrs: &[Delta<&'a Attribute>]) -> Vec<(&'a str, Option<Str>)> {
+    let mut props: Vec<(&str, Option<Str>)> = Vec::new();
+    for delta in attrs {
+        let (key, val) = match delta {
+            Delta::Add(attr) => (attr.key(), Some(attr.value())),
+            Delta::Remove(attr) => (attr.key(), None),
+        };
+        if !["checked", "selected", "value"].contains(&key) {
+            continue;
+        }
+        match props.iter_mut().find(|(k, _)| *k == key) {
+            // An added value wins over a removed one
+            Some(prop) => {
+                if val.is_some() {
+                    prop.1 = val
+                }
             }
-            Delta::Remove(event) => {
-                // When the listener is dropped it is automatically removed from the DOM
-                App::<M>::with(|app| app.remove_event_listener(&event.id()));
+            None => props.push((key, val)),
+        }
+    }
+    props
+}
+
+/// Set a form control property, if it differs from the current state
+fn set_form_property(element: &DomElement, key: &str, val: Option<&str>) {
+    use web_sys::{HtmlInputElement, HtmlOptionElement, HtmlSelectElement, HtmlTextAreaElement};
+    match key {
+        "checked" => {
+            if let Some(input) = element.dyn_ref::<HtmlInputElement>() {
+                if input.checked() != val.is_some() {
+                    input.set_checked(val.is_some())
+                }
             }
         }
+        "selected" => {
+            if let Some(option) = element.dyn_ref::<HtmlOptionElement>() {
+                if option.selected() != val.is_some() {
+                    option.set_selected(val.is_some())
+                }
+            }
+        }
+        "value" => {
+            let val = val.unwrap_or("");
+            if let Some(input) = element.dyn_ref::<HtmlInputElement>() {
+                if input.value() != val {
+                    input.set_value(val)
+                }
+            } else if let Some(textarea) = element.dyn_ref::<HtmlTextAreaElement>() {
+                if textarea.value() != val {
+                    textarea.set_value(val)
+                }
+            } else if let Some(select) = element.dyn_ref::<HtmlSelectElement>() {
+                if select.value() != val {
+                    select.set_value(val)
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn update_events<M: Model>(
+    key: Key<M>,
+    element: &DomElement,
+    events: &[EventDelta<M>],
+) -> JsResult<()> {
+    for delta in events {
+        match delta {
+            EventDelta::Add(event) => event.attach(key, element)?,
+            EventDelta::Keep { old, new } => old.transfer(new),
+        }
     }
     Ok(())
 }
@@ -896,6 +1545,9 @@ fn update_attrs(element: &DomElement, attrs: &[Delta<&Attribute>]) -> JsResult<(
             Delta::Remove(attr) => remove_attr_from_element(attr, element)?,
         }
     }
+    for (key, val) in form_properties(attrs) {
+        set_form_property(element, key, val.as_deref());
+    }
     Ok(())
 }
 
@@ -903,6 +1555,240 @@ fn update_attrs(element: &DomElement, attrs: &[Delta<&Attribute>]) -> JsResult<(
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_cmd_map() {
+        let msgs = |cmd: Cmd<String>| match cmd.0 {
+            CmdInner::Msg(msg) => vec![msg],
+            CmdInner::Multiple(cmds) => cmds
+                .into_iter()
+                .map(|cmd| match cmd.0 {
+                    CmdInner::Msg(msg) => msg,
+                    _ => panic!("expected a message"),
+                })
+                .collect(),
+            _ => panic!("expected a message"),
+        };
+        let cmd = Cmd::msg(1).map(|n: u32| n.to_string());
+        assert_eq!(msgs(cmd), vec!["1"]);
+
+        let cmd = Cmd::batch(vec![Cmd::msg(1), Cmd::msg(2)]).map(|n: u32| n.to_string());
+        assert_eq!(msgs(cmd), vec!["1", "2"]);
+
+        let cmd = Cmd::spawn(async { Cmd::msg(3) }).map(|n: u32| n.to_string());
+        let spawned = match cmd.0 {
+            CmdInner::Spawn { stream, .. } => {
+                futures::executor::block_on(stream.collect::<Vec<_>>())
+            }
+            _ => panic!("expected a future"),
+        };
+        assert_eq!(msgs(Cmd::batch(spawned)), vec!["3"]);
+    }
+
+    /// Records the messages and renders of the command loop
+    #[derive(Default)]
+    struct Recorder {
+        updates: Vec<u32>,
+        renders: u32,
+    }
+
+    impl Runner<u32> for Recorder {
+        fn update(&mut self, msg: u32) -> Cmd<u32> {
+            self.updates.push(msg);
+            // Each message below 10 is followed up by another
+            if msg < 10 {
+                Cmd::msg(msg * 10)
+            } else {
+                Cmd::none()
+            }
+        }
+
+        fn effect(&mut self, _: CmdInner<u32>) -> JsResult<Step<u32>> {
+            Ok(Step::Stop(Rendering::Needed))
+        }
+
+        fn render(&mut self) -> JsResult<()> {
+            self.renders += 1;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_batch_renders_once() {
+        let mut recorder = Recorder::default();
+        let cmd = Cmd::batch((1..=5).map(Cmd::msg));
+        update_and_render(&mut recorder, cmd).unwrap();
+        assert_eq!(recorder.updates, vec![1, 10, 2, 20, 3, 30, 4, 40, 5, 50]);
+        assert_eq!(recorder.renders, 1);
+
+        let mut recorder = Recorder::default();
+        let nested = Cmd::batch(vec![
+            Cmd::msg(1),
+            Cmd::batch(vec![Cmd::msg(2), Cmd::msg(3)]),
+        ]);
+        update_and_render(&mut recorder, nested).unwrap();
+        assert_eq!(recorder.updates, vec![1, 10, 2, 20, 3, 30]);
+        assert_eq!(recorder.renders, 1);
+
+        let mut recorder = Recorder::default();
+        update_and_render(&mut recorder, Cmd::batch(vec![Cmd(CmdInner::NoOp)])).unwrap();
+        assert_eq!(recorder.renders, 0);
+    }
+
+    #[test]
+    fn test_tasks() {
+        use futures::channel::mpsc;
+        use futures::executor::LocalPool;
+        use futures::task::LocalSpawnExt;
+
+        let mut pool = LocalPool::new();
+        let spawner = pool.spawner();
+        let mut tasks = Tasks::default();
+        let received = Rc::new(RefCell::new(Vec::new()));
+        let track = |tasks: &mut Tasks, cancel_previous| {
+            let (tx, rx) = mpsc::unbounded::<Cmd<u32>>();
+            let received = received.clone();
+            let fut = tasks.track(
+                Box::pin(rx),
+                Some("search".into()),
+                cancel_previous,
+                move |cmd| match cmd.0 {
+                    CmdInner::Msg(msg) => received.borrow_mut().push(msg),
+                    _ => panic!("expected a message"),
+                },
+            );
+            spawner.spawn_local(fut).unwrap();
+            tx
+        };
+
+        let first = track(&mut tasks, false);
+        first.unbounded_send(Cmd::msg(1)).unwrap();
+        pool.run_until_stalled();
+        assert_eq!(*received.borrow(), vec![1]);
+
+        // Like `spawn_latest`, which aborts the first task
+        let second = track(&mut tasks, true);
+        assert_eq!(tasks.running.borrow().len(), 1);
+        let _ = first.unbounded_send(Cmd::msg(2));
+        second.unbounded_send(Cmd::msg(3)).unwrap();
+        pool.run_until_stalled();
+        assert_eq!(*received.borrow(), vec![1, 3]);
+        assert!(first.is_closed());
+
+        tasks.cancel("unknown");
+        assert_eq!(tasks.running.borrow().len(), 1);
+
+        // Finished tasks remove themselves
+        drop(second);
+        pool.run_until_stalled();
+        assert!(tasks.running.borrow().is_empty());
+
+        let third = track(&mut tasks, false);
+        tasks.cancel("search");
+        assert!(tasks.running.borrow().is_empty());
+        let _ = third.unbounded_send(Cmd::msg(4));
+        pool.run_until_stalled();
+        assert_eq!(*received.borrow(), vec![1, 3]);
+    }
+
+    #[test]
+    fn test_form_properties() {
+        let input = |attrs: Vec<Attribute>| {
+            let mut elem: Element<()> = Element::tag(Tag::Input);
+            elem.attrs = attrs;
+            Html::Element(elem)
+        };
+        let props = |old: &Html<()>, new: &Html<()>| match diff_vdom(old, new) {
+            Diff::Update { attrs, .. } => form_properties(&attrs)
+                .into_iter()
+                .map(|(key, val)| (key.to_string(), val.map(|v| v.to_string())))
+                .collect::<Vec<_>>(),
+            _ => panic!("expected an update"),
+        };
+
+        // A changed value is written once, rather than reset and then set
+        let typed = input(vec![html::value("ab"), html::id("edit")]);
+        let old = input(vec![html::value("a"), html::id("edit")]);
+        assert_eq!(
+            props(&old, &typed),
+            vec![("value".to_string(), Some("ab".into()))]
+        );
+
+        let cleared = input(vec![html::id("edit")]);
+        assert_eq!(props(&old, &cleared), vec![("value".to_string(), None)]);
+
+        let checked = input(vec![html::checked(), html::id("edit")]);
+        assert_eq!(
+            props(&cleared, &checked),
+            vec![("checked".to_string(), Some("checked".into()))]
+        );
+        assert_eq!(
+            props(&checked, &cleared),
+            vec![("checked".to_string(), None)]
+        );
+
+        let renamed = input(vec![html::id("other")]);
+        assert!(props(&cleared, &renamed).is_empty());
+    }
+
+    #[test]
+    fn test_node_ref_moves_to_earlier_sibling() {
+        let node_ref = NodeRef::new();
+        let el = |node_ref: Option<&NodeRef>, children: Vec<Html<()>>| {
+            let mut elem: Element<()> = Element::tag(Tag::Div);
+            elem.node_ref = node_ref.cloned();
+            elem.children = children;
+            Html::Element(elem)
+        };
+        let old = el(None, vec![el(None, vec![]), el(Some(&node_ref), vec![])]);
+        let new = el(None, vec![el(Some(&node_ref), vec![]), el(None, vec![])]);
+
+        let children = match diff_vdom(&old, &new) {
+            Diff::Update { children, .. } => children,
+            _ => panic!("expected an update"),
+        };
+        let deltas: Vec<(u32, NodeRefDelta)> = children
+            .into_iter()
+            .map(|(ix, diff)| match diff {
+                Diff::Update {
+                    node_ref: Some(delta),
+                    ..
+                } => (ix, delta),
+                _ => panic!("expected a node ref update"),
+            })
+            .collect();
+        assert_eq!(deltas.len(), 2);
+        assert!(deltas[0].1.old.is_none() && deltas[0].1.new.unwrap().ptr_eq(&node_ref));
+        assert!(deltas[1].1.old.unwrap().ptr_eq(&node_ref) && deltas[1].1.new.is_none());
+
+        // Patch in order as `render_diff` does, with sibling indices standing in for elements
+        let slot = RefCell::new(Some(1));
+        for (ix, delta) in &deltas {
+            if delta.old.is_some() {
+                html::release(&slot, ix);
+            }
+            if delta.new.is_some() {
+                *slot.borrow_mut() = Some(*ix);
+            }
+        }
+        assert_eq!(*slot.borrow(), Some(0));
+    }
+
+    #[test]
+    fn test_history_cmds() {
+        let delta = |cmd: Cmd<()>| match cmd.0 {
+            CmdInner::Go(delta) => delta,
+            _ => panic!("expected a history move"),
+        };
+        assert_eq!(delta(Cmd::back()), -1);
+        assert_eq!(delta(Cmd::forward()), 1);
+        assert_eq!(delta(Cmd::go(-3)), -3);
+
+        match Cmd::<()>::replace_url("/items?page=2").0 {
+            CmdInner::ReplaceUrl(url) => assert_eq!(url, "/items?page=2"),
+            _ => panic!("expected a url replacement"),
+        }
+    }
+
     #[test]
     fn test_erased_eq() {
         // ErasedEq experiment
@@ -1,12 +1,18 @@
-use crate::{set_link_click_handler, App, Element, Html, JsResult, JsValue, Model, Tag, APP};
+use crate::{
+    set_link_click_handler, App, AppId, Element, Html, JsResult, JsValue, Model, Tag, Tasks,
+};
 
 use wasm_bindgen::JsCast;
 use web_sys::{Element as DomElement, HtmlDivElement};
 
-use std::collections::HashMap;
-
 /// Run a single-page application, including routing and HTTP requests
-pub fn run<M: Model>(model: M, target: &str) -> JsResult<()> {
+///
+/// The app is mounted on the element with id `target`. `run` may be called
+/// several times (with different Models, if desired) to mount several
+/// independent apps on one page, so long as each has its own target.
+///
+/// Returns a handle which can be used to unmount the app again.
+pub fn run<M: Model>(model: M, target: &str) -> JsResult<AppHandle> {
     info!("Launching application");
     // Set the hook to get sensible(ish) error messages upon panic
     console_error_panic_hook::set_once();
@@ -20,48 +26,58 @@ pub fn run<M: Model>(model: M, target: &str) -> JsResult<()> {
     let target: HtmlDivElement = target.dyn_into()?;
     let root_elem: DomElement = target.clone().dyn_into().unwrap();
     let initial = document.create_element(&Tag::Div.to_string())?;
+    let original_html = target.inner_html(); // restored when the app is unmounted
     target.set_inner_html(""); // blank the target div and create an initial root
     target.append_child(&*initial)?;
 
-    let app = App {
+    let app_id = App::register(|id| App {
+        id,
         window: window.clone(),
         target,
         model,
         current_vdom: Html::from(Element::tag(Tag::Div)), // now the dom and vdom are in sync
-        listeners: HashMap::new(),
         // TODO this could be a hashmap to reduce On^2 complexity
         subscriptions: Vec::new(),
-    };
-
-    // put app on the heap...
-    let app = Box::new(app);
-    // and leak it so we can put it in a thread-local
-    let app_ptr = Box::leak::<'static>(app) as *mut App<M> as *mut u8;
-    // super unsafe. We swap our global void pointer to point to our app.
-    // We to do it this way because it isn't possible to have
-    // generics in globals. That is, the user of the library chooses their own Model
-    // so we can't know the full type of App in advance.
-    APP.with(move |ptr| {
-        let inner = ptr as *const *mut u8 as *mut *mut u8;
-        unsafe {
-            *inner = app_ptr;
-        }
+        global_listeners: Vec::new(),
+        tasks: Tasks::default(),
+        original_html,
     });
 
-    // From this point on we only interact with App through App::with.
-    // Then it's safe, hopefully.
-    App::<M>::with(|app| {
-        app.set_popstate_handler();
-
-        let initcmd = M::init(url);
-        // Run initial command, then rerender just to be sure
-        app.loop_update(initcmd)?;
+    // From this point on we only interact with App through App::with and App::dispatch.
+    App::<M>::with(app_id, |app| {
+        app.set_popstate_handler()?;
 
         // Set the handler which intercepts all clicks and sees if they were on links, if so - intercepts)
-        let link_listener = set_link_click_handler::<M, _>(location, root_elem, M::on_url_request)?;
-        // We leak this listener because it must be valid for the life of the program
-        Box::leak(Box::new(link_listener));
+        let link_listener =
+            set_link_click_handler::<M, _>(app.key(), location, root_elem, M::on_url_request)?;
+        app.global_listeners.push(link_listener);
 
         JsResult::Ok(())
+    })??;
+
+    // Run initial command, then rerender just to be sure
+    App::<M>::dispatch(app_id, M::init(url))?;
+
+    Ok(AppHandle {
+        id: app_id,
+        unmount: App::<M>::unregister,
     })
 }
+
+/// A handle to an app started with `run`
+#[derive(Debug)]
+pub struct AppHandle {
+    id: AppId,
+    unmount: fn(AppId) -> JsResult<()>,
+}
+
+impl AppHandle {
+    /// Tear down the app.
+    ///
+    /// All event listeners are removed, subscriptions are dropped (closing sockets,
+    /// clearing timers etc), in-flight futures are cancelled and the target element
+    /// is restored to the content it had before the app was mounted.
+    pub fn unmount(self) -> JsResult<()> {
+        (self.unmount)(self.id)
+    }
+}
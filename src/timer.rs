@@ -1,9 +1,14 @@
 use std::fmt::{self, Debug};
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
 
+use futures::channel::oneshot;
 use wasm_bindgen::closure::Closure;
 use wasm_bindgen::JsCast;
 
-use crate::{Cmd, Key, Model, Subscription};
+use crate::{Cmd, Key, Model, Str, Subscription};
 
 pub struct Timer<M: Model> {
     callback_id: Option<i32>,
@@ -71,3 +76,110 @@ impl<M: Model> Drop for Timer<M> {
         }
     }
 }
+
+/// A future which resolves once a duration has elapsed. Created with `sleep`.
+///
+/// Dropping it before then cancels the underlying timeout.
+pub struct Sleep {
+    done: oneshot::Receiver<()>,
+    timeout: Option<(i32, Closure<dyn FnMut()>)>,
+}
+
+/// Wait for the given duration, e.g. within a future passed to `Cmd::spawn`
+///
+/// ```ignore
+/// Cmd::spawn(async {
+///     fig::sleep(Duration::from_secs(1)).await;
+///     Cmd::msg(Msg::Retry)
+/// })
+/// ```
+pub fn sleep(duration: Duration) -> Sleep {
+    let (tx, done) = oneshot::channel();
+    let mut tx = Some(tx);
+    let callback = Closure::wrap(Box::new(move || {
+        if let Some(tx) = tx.take() {
+            let _ = tx.send(());
+        }
+    }) as Box<dyn FnMut()>);
+    let ms = duration.as_millis().min(i32::MAX as u128) as i32;
+    let window = web_sys::window().expect("No global `window` exists");
+    let timeout = match window.set_timeout_with_callback_and_timeout_and_arguments_0(
+        callback.as_ref().unchecked_ref(),
+        ms,
+    ) {
+        Ok(id) => Some((id, callback)),
+        // Dropping the callback drops the sender, so we resolve immediately
+        Err(e) => {
+            error!("Failed to set timeout: {:?}", e);
+            None
+        }
+    };
+    Sleep { done, timeout }
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        Pin::new(&mut self.done).poll(cx).map(|_| ())
+    }
+}
+
+impl Debug for Sleep {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Sleep")
+    }
+}
+
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        if let Some((id, _)) = self.timeout {
+            if let Some(window) = web_sys::window() {
+                window.clear_timeout_with_handle(id);
+            }
+        }
+    }
+}
+
+impl<Msg: 'static> Cmd<Msg> {
+    /// Send a message to `update` once the given duration has elapsed,
+    /// e.g. to hide a notification after a few seconds.
+    ///
+    /// Like other spawned futures, it is cancelled if the app is unmounted
+    pub fn delay(duration: Duration, msg: Msg) -> Cmd<Msg> {
+        Cmd::spawn(async move {
+            sleep(duration).await;
+            Cmd::msg(msg)
+        })
+    }
+
+    /// Like `delay`, but the message can be called off with `Cmd::cancel`,
+    /// e.g. to keep a notification open while the mouse is over it
+    pub fn delay_with_id(id: impl Into<Str>, duration: Duration, msg: Msg) -> Cmd<Msg> {
+        Cmd::spawn_with_id(id, async move {
+            sleep(duration).await;
+            Cmd::msg(msg)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CmdInner;
+
+    #[test]
+    fn test_delay_with_id() {
+        match Cmd::delay_with_id("toast", Duration::from_secs(3), ()).0 {
+            CmdInner::Spawn {
+                id,
+                cancel_previous,
+                ..
+            } => {
+                assert_eq!(id.as_deref(), Some("toast"));
+                assert!(!cancel_previous);
+            }
+            _ => panic!("expected a spawned future"),
+        }
+    }
+}
@@ -1,3 +1,5 @@
+use web_sys::{AbortController, AbortSignal};
+
 use crate::{Cmd, DomElement, JsResult, JsValue, UrlRequest};
 
 pub fn get_session<T: serde::de::DeserializeOwned>() -> JsResult<T> {
@@ -54,3 +56,27 @@ pub fn get_str_prop(elem: &DomElement, key: &str) -> JsResult<String> {
     let key = JsValue::from_str(key);
     js_sys::Reflect::get(elem, &key).and_then(|val| val.as_string().ok_or(val))
 }
+
+/// An `AbortController` which aborts when dropped.
+///
+/// Create one within a spawned future and pass its `signal` to `fetch`, so the
+/// request is aborted if the future is cancelled (e.g. by `Cmd::cancel`)
+#[derive(Debug)]
+pub struct AbortOnDrop(AbortController);
+
+impl AbortOnDrop {
+    pub fn new() -> JsResult<AbortOnDrop> {
+        AbortController::new().map(AbortOnDrop)
+    }
+
+    pub fn signal(&self) -> AbortSignal {
+        self.0.signal()
+    }
+}
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        // Has no effect if the request has already completed
+        self.0.abort();
+    }
+}